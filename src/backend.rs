@@ -0,0 +1,154 @@
+use colored::*;
+use std::fs;
+use std::path::Path;
+
+use crate::{buildenv, cmake_compile, events, targets, CompileArgs};
+
+/// A build system sage can drive. The command layer only talks to this trait,
+/// so `compile`/`run` work the same whichever backend a project uses.
+pub trait BuildBackend {
+    /// Configures `build_dir` when needed and builds the project into it,
+    /// returning the captured build output.
+    fn compile(&self, build_dir: &str, args: &CompileArgs) -> Result<String, std::io::Error>;
+
+    /// The executable targets the project declares, in declaration order.
+    fn executable_targets(&self, root: &Path, project_name: &str) -> Vec<String>;
+}
+
+pub struct CMake;
+
+pub struct Meson;
+
+/// Picks the backend for the project at `root`: Meson when it has a
+/// `meson.build` and no top-level `CMakeLists.txt`, CMake otherwise.
+pub fn detect(root: &Path) -> Box<dyn BuildBackend> {
+    if is_meson_project(root) {
+        Box::new(Meson)
+    } else {
+        Box::new(CMake)
+    }
+}
+
+pub fn is_meson_project(root: &Path) -> bool {
+    root.join("meson.build").exists() && !root.join("CMakeLists.txt").exists()
+}
+
+impl BuildBackend for CMake {
+    fn compile(&self, build_dir: &str, args: &CompileArgs) -> Result<String, std::io::Error> {
+        cmake_compile(build_dir, args)
+    }
+
+    fn executable_targets(&self, root: &Path, project_name: &str) -> Vec<String> {
+        targets::executable_targets(root, project_name)
+    }
+}
+
+impl BuildBackend for Meson {
+    fn compile(&self, build_dir: &str, args: &CompileArgs) -> Result<String, std::io::Error> {
+        if !args.features.is_empty() || args.no_default_features {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "--features and --no-default-features are not supported for Meson projects yet."));
+        }
+        if args.triple.is_some() {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "--triple needs the Conan integration, which only CMake projects have; Meson cross-compiles with --cross-file."));
+        }
+        if args.trace {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "--trace records CMake's configure step, which Meson projects don't have."));
+        }
+        if !args.werror_scope.is_empty() {
+            events::status(format!("{} --werror-scope only applies to CMake projects, ignoring it", "Warning:".yellow()));
+        }
+        if args.pgo_generate || args.pgo_use {
+            events::status(format!("{} --pgo-generate/--pgo-use only apply to CMake projects; Meson has -Db_pgo=generate/use", "Warning:".yellow()));
+        }
+        if args.depfile_check {
+            events::status(format!("{} --depfile-check only applies to CMake projects, ignoring it", "Warning:".yellow()));
+        }
+        if args.time_trace {
+            events::status(format!("{} --time-trace only applies to CMake projects; add -ftime-trace to the Meson cpp_args instead", "Warning:".yellow()));
+        }
+        if args.sanitize.is_some() {
+            events::status(format!("{} --sanitize only applies to CMake projects; Meson has -Db_sanitize=address/undefined/thread", "Warning:".yellow()));
+        }
+        if args.link_jobs.is_some() {
+            events::status(format!("{} --link-jobs only applies to CMake projects; Meson sets link pools with backend_max_links", "Warning:".yellow()));
+        }
+        if args.color_diagnostics {
+            events::status(format!("{} --color-diagnostics only applies to CMake projects; Meson has -Db_colorout=always", "Warning:".yellow()));
+        }
+        if args.print_compiler_version {
+            events::status(format!("{} --print-compiler-version only applies to CMake projects; 'meson introspect --compilers' lists Meson's", "Warning:".yellow()));
+        }
+        if args.max_errors.is_some() {
+            events::status(format!("{} --max-errors only applies to CMake projects; add -fmax-errors=N (GCC) or -ferror-limit=N (Clang) to the Meson cpp_args instead", "Warning:".yellow()));
+        }
+
+        if Path::new(build_dir).join("meson-private").join("coredata.dat").exists() {
+            events::emit("configure-skipped", serde_json::json!({ "build_dir": build_dir }));
+            events::status("Meson build directory is already set up, skipping setup.".dimmed());
+            if let Some(build_type) = args.build_type() {
+                let mut configure = buildenv::tool_command("meson");
+                configure.args(["configure", build_dir, &format!("-Dbuildtype={}", build_type.to_lowercase())]);
+                events::command(&configure);
+                let configure_output = configure.output()?;
+                if !configure_output.status.success() {
+                    let stderr = String::from_utf8_lossy(&configure_output.stderr);
+                    return Err(std::io::Error::other(format!("Setting the Meson build type failed:\n{}", stderr)));
+                }
+            }
+        } else {
+            events::emit("configure-started", serde_json::json!({ "build_dir": build_dir }));
+            events::status("Configuring project with Meson...".green());
+            let mut setup = buildenv::tool_command("meson");
+            setup.args(["setup", build_dir]);
+            if let Some(build_type) = args.build_type() {
+                setup.arg(format!("--buildtype={}", build_type.to_lowercase()));
+            }
+            events::command(&setup);
+            let setup_output = setup.output()?;
+            events::emit("configure-finished", serde_json::json!({ "success": setup_output.status.success() }));
+            if !setup_output.status.success() {
+                let stderr = String::from_utf8_lossy(&setup_output.stderr);
+                let stdout = String::from_utf8_lossy(&setup_output.stdout);
+                return Err(std::io::Error::other(format!("Meson setup failed:\n{}{}", stdout, stderr)));
+            }
+            if events::show_tool_output(args.summary_only) {
+                println!("{}", String::from_utf8_lossy(&setup_output.stdout));
+            }
+        }
+
+        events::emit("build-started", serde_json::json!({ "build_dir": build_dir }));
+        events::status("Compiling project with Meson...".green());
+        let mut build = buildenv::tool_command("meson");
+        build.args(["compile", "-C", build_dir, "-j", &args.jobs().to_string()]);
+        events::command(&build);
+        let build_output = build.output()?;
+        if !build_output.status.success() {
+            let stderr = String::from_utf8_lossy(&build_output.stderr);
+            let stdout = String::from_utf8_lossy(&build_output.stdout);
+            return Err(std::io::Error::other(format!("Meson build failed:\n{}{}", stdout, stderr)));
+        }
+        let stdout = String::from_utf8_lossy(&build_output.stdout);
+        let stderr = String::from_utf8_lossy(&build_output.stderr);
+        if events::show_tool_output(args.summary_only) {
+            println!("{}", stdout);
+        }
+
+        Ok(format!("{}{}", stdout, stderr))
+    }
+
+    fn executable_targets(&self, root: &Path, _project_name: &str) -> Vec<String> {
+        let Ok(content) = fs::read_to_string(root.join("meson.build")) else {
+            return Vec::new();
+        };
+        let mut targets = Vec::new();
+        let mut rest = content.as_str();
+        while let Some(start) = rest.find("executable(") {
+            rest = &rest[start + "executable(".len()..];
+            let name = rest.trim_start().trim_start_matches(['\'', '"']);
+            if let Some(end) = name.find(['\'', '"']) {
+                targets.push(name[..end].to_string());
+            }
+        }
+        targets
+    }
+}
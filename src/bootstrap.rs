@@ -0,0 +1,75 @@
+use colored::*;
+use std::process::Command;
+
+use crate::{confirm, events, find_program, RequiredTool, REQUIRED_TOOLS};
+
+/// Installs the required tools missing from PATH with the platform's package
+/// manager. The commands are listed first and nothing runs without a "y" on
+/// the terminal; declining leaves everything as it was.
+pub fn install_missing_tools() -> Result<(), std::io::Error> {
+    let missing: Vec<&RequiredTool> = REQUIRED_TOOLS.iter().filter(|tool| find_program(tool.name).is_none()).collect();
+    if missing.is_empty() {
+        events::status("All required tools are installed.".dimmed());
+        return Ok(());
+    }
+
+    let mut plan = Vec::new();
+    for tool in missing {
+        match install_command(tool) {
+            Some(command) => plan.push((tool, command)),
+            None => {
+                events::status(format!("{} {} is missing and can't be installed automatically", "Warning:".yellow(), tool.name));
+                events::status(format!("  {}", tool.install_hint.cyan()));
+            }
+        }
+    }
+    if plan.is_empty() {
+        return Ok(());
+    }
+
+    events::status("Missing tools will be installed with:");
+    for (_, command) in &plan {
+        events::status(format!("  {}", command.join(" ").cyan()));
+    }
+    if !confirm("Refusing to install tools without confirmation; run sage from a terminal or install them yourself.")? {
+        events::status(format!("{} No tools were installed.", "Note:".yellow()));
+        return Ok(());
+    }
+
+    for (tool, command) in &plan {
+        events::status(format!("Installing {}...", tool.name.bold()));
+        let status = Command::new(&command[0]).args(&command[1..]).status().map_err(|e| {
+            std::io::Error::new(e.kind(), format!("Could not run {}: {}", command[0], e))
+        })?;
+        if !status.success() {
+            return Err(std::io::Error::other(format!("Installing {} failed ({})", tool.name, status)));
+        }
+        // winget and pip --user can install outside this process's PATH.
+        if find_program(tool.name).is_none() {
+            events::status(format!("{} {} was installed but isn't in PATH yet; open a new terminal if the build can't find it", "Warning:".yellow(), tool.name));
+        }
+    }
+    events::status(format!("{} Missing tools installed.", "Success:".green()));
+    Ok(())
+}
+
+/// The command installing `tool` here, or `None` when there is no package
+/// manager sage knows how to drive.
+fn install_command(tool: &RequiredTool) -> Option<Vec<String>> {
+    let words = |command: &str| command.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+    // Conan is a Python package everywhere; Windows installs all of them with winget.
+    if cfg!(target_os = "windows") || tool.name == "conan" {
+        let command = words(tool.install_hint);
+        return find_program(&command[0]).is_some().then_some(command);
+    }
+    if cfg!(target_os = "macos") {
+        // Homebrew's clang comes with the llvm formula.
+        let formula = if tool.name == "clang" { "llvm" } else { tool.name };
+        return find_program("brew").is_some().then(|| words(&format!("brew install {}", formula)));
+    }
+    let package = tool.apt_package?;
+    find_program("apt-get")?;
+    // Containers often run as root without sudo.
+    let sudo = if find_program("sudo").is_some() { "sudo " } else { "" };
+    Some(words(&format!("{}apt-get install -y {}", sudo, package)))
+}
@@ -0,0 +1,106 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// Where `sage install` puts Conan's generated files.
+const CONAN_OUTPUT_DIR: &str = "packages/install";
+
+/// Creates a `Command` for a build tool with Conan's build environment applied,
+/// so tools that come from `tool_requires` (e.g. cmake/ninja living in the
+/// Conan cache) are found even though they aren't on the user's PATH.
+pub fn tool_command(program: &str) -> Command {
+    let mut command = Command::new(program);
+    if let Some(vars) = conan_build_env() {
+        command.envs(vars);
+    }
+    command
+}
+
+/// The environment variables Conan's `conanbuild` script would set, captured
+/// once by sourcing the script in a shell and diffing the resulting
+/// environment against ours. `None` when the install step generated no script.
+pub fn conan_build_env() -> Option<&'static HashMap<String, String>> {
+    static ENV: OnceLock<Option<HashMap<String, String>>> = OnceLock::new();
+    ENV.get_or_init(|| capture_env(Path::new(CONAN_OUTPUT_DIR))).as_ref()
+}
+
+fn capture_env(dir: &Path) -> Option<HashMap<String, String>> {
+    let output = if cfg!(target_os = "windows") {
+        let script = dir.join("conanbuild.bat");
+        if !script.exists() {
+            return None;
+        }
+        Command::new("cmd").arg("/C").arg(format!("call \"{}\" >NUL && set", script.display())).output().ok()?
+    } else {
+        let script = dir.join("conanbuild.sh");
+        if !script.exists() {
+            return None;
+        }
+        Command::new("sh").arg("-c").arg(format!(". \"{}\" >/dev/null && env", script.display())).output().ok()?
+    };
+    if !output.status.success() {
+        return None;
+    }
+
+    let vars = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        // Skip continuation lines of multi-line values.
+        .filter(|(key, _)| !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+        .filter(|(key, value)| env::var(key).ok().as_deref() != Some(*value))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    Some(vars)
+}
+
+/// Name fragments marking variables whose values `--export-build-env` hides.
+const SENSITIVE_NAME_PARTS: &[&str] = &["TOKEN", "SECRET", "PASSWORD", "PASSWD", "API_KEY", "PRIVATE_KEY", "CREDENTIAL", "AUTH", "ACCESS_KEY", "COOKIE"];
+
+/// Writes the environment build tools run with to `path`: sage's own
+/// environment, then what Conan's build environment changes, then the
+/// variables sage sets itself (`sage_vars`). Values of variables that look
+/// like secrets are replaced with `<redacted>` when `redact` is set.
+pub fn export(path: &Path, sage_vars: &[(&str, &str)], redact: bool) -> Result<(), std::io::Error> {
+    let shown = |key: &str, value: &str| {
+        let upper = key.to_ascii_uppercase();
+        if redact && SENSITIVE_NAME_PARTS.iter().any(|part| upper.contains(part)) {
+            format!("{}=<redacted>\n", key)
+        } else {
+            format!("{}={}\n", key, value)
+        }
+    };
+
+    let mut content = String::from("# Inherited environment\n");
+    let inherited: BTreeMap<String, String> = env::vars_os()
+        .map(|(key, value)| (key.to_string_lossy().into_owned(), value.to_string_lossy().into_owned()))
+        .collect();
+    for (key, value) in &inherited {
+        content.push_str(&shown(key, value));
+    }
+
+    content.push_str(&format!("\n# Conan build environment ({}/conanbuild)\n", CONAN_OUTPUT_DIR));
+    match conan_build_env() {
+        Some(vars) => {
+            for (key, value) in vars.iter().collect::<BTreeMap<_, _>>() {
+                content.push_str(&shown(key, value));
+            }
+        }
+        None => content.push_str("# (none)\n"),
+    }
+
+    content.push_str("\n# Set by sage\n");
+    if sage_vars.is_empty() {
+        content.push_str("# (none)\n");
+    }
+    for (key, value) in sage_vars {
+        content.push_str(&shown(key, value));
+    }
+
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)
+}
@@ -0,0 +1,145 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The generator sage configures with unless `sage.toml` picks another.
+pub const DEFAULT_GENERATOR: &str = "Ninja";
+
+/// Whether `generator` keeps every build type in one build tree, putting the
+/// binaries of each configuration in a `<Config>/` subdirectory.
+pub fn is_multi_config(generator: &str) -> bool {
+    generator == "Ninja Multi-Config" || generator == "Xcode" || generator.starts_with("Visual Studio")
+}
+
+/// The presets file `sage new` generates and `compile --preset` uses.
+pub const PRESETS_FILE: &str = "CMakePresets.json";
+
+/// The `binaryDir` of the configure preset `name` in the project's
+/// `CMakePresets.json`, with `${sourceDir}/` stripped (`Some("")` when the
+/// preset inherits it). Fails when the file or the preset is missing.
+pub fn configure_preset_binary_dir(root: &Path, name: &str) -> Result<String, std::io::Error> {
+    let content = fs::read_to_string(root.join(PRESETS_FILE)).map_err(|e| {
+        std::io::Error::new(e.kind(), format!("{} not found; new projects get one, or write your own with '{}' configure and build presets.", PRESETS_FILE, name))
+    })?;
+    let presets: serde_json::Value = serde_json::from_str(&content).map_err(|e| std::io::Error::other(format!("Could not parse {}: {}", PRESETS_FILE, e)))?;
+    let preset = presets["configurePresets"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|preset| preset["name"] == name)
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} has no configure preset named '{}'.", PRESETS_FILE, name)))?;
+    let binary_dir = preset["binaryDir"].as_str().unwrap_or("");
+    Ok(binary_dir.strip_prefix("${sourceDir}/").unwrap_or(binary_dir).to_string())
+}
+
+/// Lists every `CMakeLists.txt` in the project, skipping hidden directories and
+/// the generated `build`/`packages` trees.
+pub fn cmake_lists_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return files;
+    };
+    let mut entries: Vec<_> = entries.map_while(Result::ok).collect();
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        if path.is_dir() {
+            if !file_name.starts_with('.') && file_name != "build" && file_name != "packages" {
+                files.extend(cmake_lists_files(&path));
+            }
+        } else if file_name == "CMakeLists.txt" {
+            files.push(path);
+        }
+    }
+    files
+}
+
+/// Returns the argument lists of every `<command>(...)` call in a CMake
+/// script, with `#` comments stripped.
+pub fn command_arguments(content: &str, command: &str) -> Vec<Vec<String>> {
+    let code: String = content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let pattern = format!("{}(", command);
+    let mut calls = Vec::new();
+    let mut rest = code.as_str();
+    while let Some(start) = rest.find(&pattern) {
+        let preceded_by_identifier = rest[..start].chars().next_back().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_');
+        rest = &rest[start + pattern.len()..];
+        if preceded_by_identifier {
+            continue;
+        }
+        let arguments = &rest[..rest.find(')').unwrap_or(rest.len())];
+        calls.push(split_arguments(arguments));
+    }
+    calls
+}
+
+/// Splits CMake arguments on whitespace, keeping quoted arguments together
+/// (without their quotes).
+fn split_arguments(arguments: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for c in arguments.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    result.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        result.push(current);
+    }
+    result
+}
+
+/// The `option(<name> "<help>" [value])` declarations across the project.
+pub fn declared_options(root: &Path) -> Vec<String> {
+    let mut options = Vec::new();
+    for file in cmake_lists_files(root) {
+        let Ok(content) = fs::read_to_string(&file) else {
+            continue;
+        };
+        for arguments in command_arguments(&content, "option") {
+            if let Some(name) = arguments.into_iter().next() {
+                if !options.contains(&name) {
+                    options.push(name);
+                }
+            }
+        }
+    }
+    options
+}
+
+/// Locates the call listing `target`'s sources: its `target_sources(<target>
+/// ...)` call when it has one, otherwise the `add_executable(<target> ...)` or
+/// `add_library(<target> ...)` defining it. Returns the byte range of the
+/// call's arguments (between the parentheses) within `content`.
+pub fn target_arguments_span(content: &str, target: &str) -> Option<std::ops::Range<usize>> {
+    for command in ["target_sources(", "add_executable(", "add_library("] {
+        let mut offset = 0;
+        while let Some(found) = content[offset..].find(command) {
+            let start = offset + found + command.len();
+            offset = start;
+            let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+            if content[line_start..start].contains('#') {
+                continue;
+            }
+            let end = start + content[start..].find(')')?;
+            let name = content[start..end].split_whitespace().next();
+            if name == Some(target) {
+                return Some(start..end);
+            }
+        }
+    }
+    None
+}
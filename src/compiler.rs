@@ -0,0 +1,113 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// The C++ compiler family a build uses, which decides how flags are spelled.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Compiler {
+    Gcc,
+    Clang,
+    Msvc,
+    Unknown,
+}
+
+impl Compiler {
+    /// The flag that keeps colored diagnostics when output is captured.
+    pub fn color_diagnostics_flag(self) -> Option<&'static str> {
+        match self {
+            Compiler::Gcc => Some("-fdiagnostics-color=always"),
+            Compiler::Clang => Some("-fcolor-diagnostics"),
+            Compiler::Msvc | Compiler::Unknown => None,
+        }
+    }
+
+    /// The flag that stops compilation after `limit` errors.
+    pub fn max_errors_flag(self, limit: u32) -> Option<String> {
+        match self {
+            Compiler::Gcc => Some(format!("-fmax-errors={}", limit)),
+            Compiler::Clang => Some(format!("-ferror-limit={}", limit)),
+            Compiler::Msvc | Compiler::Unknown => None,
+        }
+    }
+}
+
+/// Detects the compiler from the configured CMake cache in `build_dir`, or by
+/// probing the compiler CMake would pick (`$CXX`, else the platform default)
+/// when the project hasn't been configured yet.
+pub fn detect(build_dir: &Path) -> Compiler {
+    if let Some(id) = cached_compiler_id(build_dir) {
+        return from_compiler_id(&id);
+    }
+    probe()
+}
+
+/// Describes the C++ compiler CMake selected for `build_dir`, e.g.
+/// `GNU 12.2.0 (/usr/bin/c++)`, or `None` before the project is configured.
+pub fn configured_description(build_dir: &Path) -> Option<String> {
+    let id = cached_compiler_id(build_dir)?;
+    let path = cache_value(build_dir, "CMAKE_CXX_COMPILER").unwrap_or_default();
+    let version = configured_version(build_dir).unwrap_or_else(|| "unknown version".to_string());
+    Some(format!("{} {} ({})", id, version, path))
+}
+
+/// Reads `CMAKE_CXX_COMPILER_VERSION` from the `CMakeCXXCompiler.cmake` that
+/// CMake writes under `CMakeFiles/<cmake version>/` while configuring.
+fn configured_version(build_dir: &Path) -> Option<String> {
+    let entries = fs::read_dir(build_dir.join("CMakeFiles")).ok()?;
+    entries.map_while(Result::ok).find_map(|entry| {
+        let content = fs::read_to_string(entry.path().join("CMakeCXXCompiler.cmake")).ok()?;
+        content.lines().find_map(|line| {
+            let value = line.trim().strip_prefix("set(CMAKE_CXX_COMPILER_VERSION ")?;
+            Some(value.trim_end_matches(')').trim_matches('"').to_string())
+        })
+    })
+}
+
+/// Reads `CMAKE_CXX_COMPILER_ID` from `build_dir/CMakeCache.txt`.
+fn cached_compiler_id(build_dir: &Path) -> Option<String> {
+    cache_value(build_dir, "CMAKE_CXX_COMPILER_ID").filter(|id| !id.is_empty())
+}
+
+/// Reads a `NAME:TYPE=value` entry from `build_dir/CMakeCache.txt`.
+pub fn cache_value(build_dir: &Path, name: &str) -> Option<String> {
+    let cache = fs::read_to_string(build_dir.join("CMakeCache.txt")).ok()?;
+    cache.lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        (key.split(':').next() == Some(name)).then(|| value.trim().to_string())
+    })
+}
+
+fn from_compiler_id(id: &str) -> Compiler {
+    match id {
+        "GNU" => Compiler::Gcc,
+        "Clang" | "AppleClang" | "IntelLLVM" => Compiler::Clang,
+        "MSVC" => Compiler::Msvc,
+        _ => Compiler::Unknown,
+    }
+}
+
+/// The C++ compiler CMake would pick: `$CXX` (minus any launcher such as
+/// ccache), else the platform default.
+pub fn cxx_program() -> String {
+    let default = if cfg!(target_os = "windows") { "cl" } else { "c++" };
+    let cxx = env::var("CXX").unwrap_or_else(|_| default.to_string());
+    cxx.split_whitespace().last().unwrap_or(default).to_string()
+}
+
+fn probe() -> Compiler {
+    let Ok(output) = Command::new(cxx_program()).arg("--version").output() else {
+        return Compiler::Unknown;
+    };
+    // cl.exe rejects --version but still prints its banner on stderr.
+    let banner = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    if banner.contains("clang") {
+        Compiler::Clang
+    } else if banner.contains("Microsoft") {
+        Compiler::Msvc
+    } else if banner.contains("Free Software Foundation") || banner.contains("GCC") {
+        Compiler::Gcc
+    } else {
+        Compiler::Unknown
+    }
+}
@@ -0,0 +1,179 @@
+use colored::*;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+use std::time::SystemTime;
+
+use crate::events;
+
+#[cfg(unix)]
+use regex::Regex;
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::path::PathBuf;
+
+#[cfg(unix)]
+use crate::{describe_exit, find_program};
+#[cfg(not(unix))]
+use crate::program_command;
+
+/// The command launching the built program with the core file size limit
+/// raised, so a crash leaves a core dump behind. Falls back to the plain
+/// command where core dumps aren't supported.
+#[cfg(unix)]
+pub fn command(exe_path: &Path, wrapper: &[String]) -> Command {
+    // Raise the soft limit through a shell so only the program is affected;
+    // when "unlimited" exceeds the hard limit, go as high as allowed.
+    let mut command = Command::new("sh");
+    command
+        .args(["-c", r#"ulimit -c unlimited 2>/dev/null || ulimit -c "$(ulimit -H -c)"; exec "$@""#, "sh"])
+        .args(wrapper)
+        .arg(exe_path);
+    command
+}
+
+#[cfg(not(unix))]
+pub fn command(exe_path: &Path, wrapper: &[String]) -> Command {
+    events::status(format!("{} --capture-core is only supported on Unix, running without it", "Warning:".yellow()));
+    program_command(exe_path, wrapper)
+}
+
+/// After a crash, reports where the core file went and, with `open`, loads it
+/// into gdb (or lldb) for post-mortem debugging.
+#[cfg(unix)]
+pub fn report(status: &ExitStatus, exe_path: &Path, started: SystemTime, open: bool) -> Result<(), std::io::Error> {
+    use std::os::unix::process::ExitStatusExt;
+
+    if status.signal().is_none() {
+        return Ok(());
+    }
+    println!("{}", format!("The program crashed: {}", describe_exit(status)).red());
+
+    let pattern = core_pattern();
+    if let Some(handler) = pattern.strip_prefix('|') {
+        print_handler_hint(handler.trim(), exe_path);
+        return Ok(());
+    }
+    if !status.core_dumped() {
+        events::status(format!("{} No core file was written (core pattern: {})", "Note:".yellow(), pattern));
+        events::status(format!("  {}", "Check that the hard limit allows core dumps: ulimit -H -c".cyan()));
+        return Ok(());
+    }
+
+    let Some(core) = find_core_file(&pattern, started) else {
+        events::status(format!("{} A core dump was written, but sage couldn't locate it (core pattern: {})", "Note:".yellow(), pattern));
+        return Ok(());
+    };
+    println!("{} {}", "Core file:".green(), core.display());
+
+    if !open {
+        println!("  {}", format!("Inspect it with: gdb {} {}", exe_path.display(), core.display()).cyan());
+        return Ok(());
+    }
+    open_in_debugger(exe_path, &core)
+}
+
+#[cfg(not(unix))]
+pub fn report(_status: &ExitStatus, _exe_path: &Path, _started: SystemTime, _open: bool) -> Result<(), std::io::Error> {
+    Ok(())
+}
+
+/// The kernel's core file name template: `/proc/sys/kernel/core_pattern` on
+/// Linux, `kern.corefile` on macOS, and the traditional `core` elsewhere.
+#[cfg(unix)]
+fn core_pattern() -> String {
+    if let Ok(pattern) = fs::read_to_string("/proc/sys/kernel/core_pattern") {
+        return pattern.trim().to_string();
+    }
+    Command::new("sysctl")
+        .args(["-n", "kern.corefile"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|pattern| !pattern.is_empty())
+        .unwrap_or_else(|| "core".to_string())
+}
+
+/// Core dumps piped to a handler never reach the file system directly, so
+/// point at the handler's own tooling instead.
+#[cfg(unix)]
+fn print_handler_hint(handler: &str, exe_path: &Path) {
+    if handler.contains("systemd-coredump") {
+        events::status(format!("{} The core dump was handed to systemd-coredump", "Note:".yellow()));
+        events::status(format!("  {}", format!("Inspect it with: coredumpctl gdb {}", exe_path.display()).cyan()));
+    } else if handler.contains("apport") {
+        events::status(format!("{} The core dump was handed to apport, which only keeps crashes of packaged programs", "Note:".yellow()));
+        events::status(format!("  {}", "Write core files instead with: sudo sysctl -w kernel.core_pattern=core".cyan()));
+    } else {
+        events::status(format!("{} Core dumps are piped to: {}", "Note:".yellow(), handler));
+    }
+}
+
+/// Finds the newest core file written since `started` that matches the core
+/// pattern, treating its `%` specifiers (PID, executable name, time, ...) as
+/// wildcards. Relative patterns resolve against the program's working
+/// directory, which is sage's own.
+#[cfg(unix)]
+fn find_core_file(pattern: &str, started: SystemTime) -> Option<PathBuf> {
+    let pattern = Path::new(pattern);
+    let dir = match pattern.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    if dir.to_string_lossy().contains('%') {
+        return None;
+    }
+    let name = name_regex(&pattern.file_name()?.to_string_lossy());
+    // File timestamps come from the kernel's coarse clock, which can lag
+    // behind `started` by a few milliseconds.
+    let started = started - std::time::Duration::from_secs(1);
+
+    fs::read_dir(&dir)
+        .ok()?
+        .flatten()
+        .filter(|entry| name.is_match(&entry.file_name().to_string_lossy()))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .filter(|(modified, _)| *modified >= started)
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+}
+
+/// Turns a core pattern file name into a regex. `kernel.core_uses_pid`
+/// appends `.<pid>` to patterns without `%p`, so that suffix is optional.
+#[cfg(unix)]
+fn name_regex(name: &str) -> Regex {
+    let mut regex = String::from("^");
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            regex.push_str(&regex::escape(&c.to_string()));
+            continue;
+        }
+        match chars.next() {
+            Some('%') => regex.push('%'),
+            Some(_) => regex.push_str(".*"),
+            None => {}
+        }
+    }
+    regex.push_str(r"(\.\d+)?$");
+    Regex::new(&regex).expect("core pattern regex is escaped")
+}
+
+#[cfg(unix)]
+fn open_in_debugger(exe_path: &Path, core: &Path) -> Result<(), std::io::Error> {
+    let mut debugger = if find_program("gdb").is_some() {
+        let mut command = Command::new("gdb");
+        command.arg(exe_path).arg(core);
+        command
+    } else if find_program("lldb").is_some() {
+        let mut command = Command::new("lldb");
+        command.arg(exe_path).arg("--core").arg(core);
+        command
+    } else {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "Neither gdb nor lldb was found in PATH to open the core file."));
+    };
+    events::status("Opening the core file in the debugger...".green());
+    debugger.status()?;
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use colored::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{events, find_program};
+
+/// Where generated cross profiles are written, one per triple.
+const PROFILES_DIR: &str = "packages/profiles";
+
+/// A cross-compilation target given as a GNU-style triple, e.g.
+/// `aarch64-linux-gnu` or `x86_64-w64-mingw32`, with the matching Conan
+/// settings and CMake system names.
+#[derive(Clone, Debug)]
+pub struct Triple {
+    pub triple: String,
+    /// Conan's `arch` setting (`armv8` for aarch64).
+    conan_arch: &'static str,
+    /// Conan's `os` setting.
+    conan_os: &'static str,
+    /// `CMAKE_SYSTEM_NAME`.
+    system_name: &'static str,
+    /// `CMAKE_SYSTEM_PROCESSOR`, the triple's own architecture.
+    processor: String,
+}
+
+/// Parses `--triple`, rejecting architectures and systems sage can't map to
+/// Conan settings.
+pub fn parse_triple(value: &str) -> Result<Triple, String> {
+    let parts: Vec<&str> = value.split('-').collect();
+    if parts.len() < 2 || parts.iter().any(|part| part.is_empty()) {
+        return Err(format!("'{}' is not a target triple (expected e.g. aarch64-linux-gnu)", value));
+    }
+    let arch = parts[0];
+    let hard_float = value.ends_with("hf");
+    let conan_arch = match arch {
+        "x86_64" | "amd64" => "x86_64",
+        "i386" | "i486" | "i586" | "i686" => "x86",
+        "aarch64" | "arm64" => "armv8",
+        "riscv64" => "riscv64",
+        "riscv32" => "riscv32",
+        "powerpc64le" | "ppc64le" => "ppc64le",
+        "s390x" => "s390x",
+        arch if arch.starts_with("arm") && hard_float => "armv7hf",
+        arch if arch.starts_with("arm") => "armv7",
+        arch => return Err(format!("Unsupported architecture '{}' in '{}'", arch, value)),
+    };
+    let has = |name: &str| parts[1..].iter().any(|part| part.starts_with(name));
+    let (conan_os, system_name) = if has("linux") {
+        ("Linux", "Linux")
+    } else if has("mingw") || has("windows") {
+        ("Windows", "Windows")
+    } else if has("darwin") || has("apple") || has("macos") {
+        ("Macos", "Darwin")
+    } else if has("freebsd") {
+        ("FreeBSD", "FreeBSD")
+    } else if has("none") || has("elf") || has("eabi") {
+        ("baremetal", "Generic")
+    } else {
+        return Err(format!("Unsupported operating system in '{}' (expected linux, mingw32, darwin, freebsd or none)", value));
+    };
+    Ok(Triple { triple: value.to_string(), conan_arch, conan_os, system_name, processor: arch.to_string() })
+}
+
+impl Triple {
+    /// Conan's output folder for this target, so its toolchain never
+    /// replaces the native one in `packages/install`.
+    pub fn install_dir(&self) -> String {
+        format!("packages/install-{}", self.triple)
+    }
+
+    /// The system CMake builds for, passed next to the Conan toolchain.
+    pub fn cmake_defines(&self) -> Vec<String> {
+        vec![format!("-DCMAKE_SYSTEM_NAME={}", self.system_name), format!("-DCMAKE_SYSTEM_PROCESSOR={}", self.processor)]
+    }
+
+    /// The Conan host profile for this target: a profile of the same name
+    /// if Conan or the project (`profiles/<triple>`) has one, otherwise the
+    /// one generated into `packages/profiles/` from the triple (once, so it
+    /// can be edited).
+    pub fn host_profile(&self) -> Result<String, std::io::Error> {
+        let project_profile = Path::new("profiles").join(&self.triple);
+        if project_profile.is_file() {
+            return Ok(project_profile.to_string_lossy().into_owned());
+        }
+        if let Ok(output) = Command::new("conan").args(["profile", "list"]).output() {
+            if String::from_utf8_lossy(&output.stdout).lines().any(|line| line.trim() == self.triple) {
+                return Ok(self.triple.clone());
+            }
+        }
+        let path = PathBuf::from(PROFILES_DIR).join(&self.triple);
+        if path.is_file() {
+            return Ok(path.to_string_lossy().into_owned());
+        }
+        fs::create_dir_all(PROFILES_DIR)?;
+        fs::write(&path, self.generated_profile())?;
+        events::status(format!("{} Generated Conan profile {} for {}", "Note:".yellow(), path.display(), self.triple));
+        Ok(path.to_string_lossy().into_owned())
+    }
+
+    /// The default profile with the target's `os` and `arch`, compiled with
+    /// the `<triple>-gcc`/`<triple>-g++` cross toolchain when it's in PATH.
+    fn generated_profile(&self) -> String {
+        let mut profile = format!("# Generated by cppsage for {}; edit freely or copy it to profiles/{}.\ninclude(default)\n\n[settings]\nos={}\narch={}\n", self.triple, self.triple, self.conan_os, self.conan_arch);
+        let (cc, cxx) = (format!("{}-gcc", self.triple), format!("{}-g++", self.triple));
+        if find_program(&cxx).is_some() {
+            profile.push_str(&format!("\n[conf]\ntools.build:compiler_executables={{\"c\": \"{}\", \"cpp\": \"{}\"}}\n", cc, cxx));
+        } else {
+            events::status(format!("{} {} not found in PATH, the profile uses the default compiler", "Warning:".yellow(), cxx));
+            events::status(format!("  {}", format!("Install a cross compiler for {}, or set tools.build:compiler_executables in the profile", self.triple).cyan()));
+        }
+        profile
+    }
+}
@@ -0,0 +1,70 @@
+use std::path::Path;
+use std::process::Command;
+
+use crate::find_program;
+
+/// The debuggers `sage debug` knows how to launch.
+pub const DEBUGGERS: &[&str] = &["gdb", "lldb", "cdb", "devenv"];
+
+/// The debuggers tried, in order, when `--debugger` isn't given: lldb comes
+/// first on macOS, where it ships with the Xcode tools and gdb needs code
+/// signing; Windows gets cdb, then Visual Studio.
+fn platform_defaults() -> &'static [&'static str] {
+    if cfg!(target_os = "macos") {
+        &["lldb", "gdb"]
+    } else if cfg!(target_os = "windows") {
+        &["cdb", "devenv"]
+    } else {
+        &["gdb", "lldb"]
+    }
+}
+
+/// Picks the debugger to launch: the `requested` one, or the first platform
+/// default found in PATH. Fails with an install hint when there is none.
+pub fn find(requested: Option<&str>) -> Result<&'static str, std::io::Error> {
+    let candidates: Vec<&'static str> = match requested {
+        Some(requested) => DEBUGGERS.iter().copied().filter(|debugger| *debugger == requested).collect(),
+        None => platform_defaults().to_vec(),
+    };
+    if let Some(debugger) = candidates.iter().find(|debugger| find_program(debugger).is_some()) {
+        return Ok(debugger);
+    }
+    let first = candidates.first().copied().unwrap_or("gdb");
+    Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!(
+        "No debugger found in PATH (looked for {}). To get {}: {}",
+        candidates.join(", "),
+        first,
+        install_hint(first)
+    )))
+}
+
+/// The command running `exe_path` with `program_args` under `debugger`. The
+/// debugger inherits the terminal so the session is interactive.
+pub fn command(debugger: &str, exe_path: &Path, program_args: &[String]) -> Command {
+    let mut command = Command::new(debugger);
+    match debugger {
+        "gdb" => {
+            command.arg("--args");
+        }
+        "lldb" => {
+            command.arg("--");
+        }
+        "devenv" => {
+            command.arg("/debugexe");
+        }
+        _ => {}
+    }
+    command.arg(exe_path).args(program_args);
+    command
+}
+
+fn install_hint(debugger: &str) -> &'static str {
+    match debugger {
+        "lldb" if cfg!(target_os = "macos") => "xcode-select --install",
+        "lldb" => "sudo apt install lldb",
+        "gdb" if cfg!(target_os = "macos") => "brew install gdb",
+        "cdb" => "install the Windows SDK with its Debugging Tools for Windows",
+        "devenv" => "winget install Microsoft.VisualStudio.2022.Community",
+        _ => "sudo apt install gdb",
+    }
+}
@@ -0,0 +1,82 @@
+use colored::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::{buildenv, cmake, collect_sources, events};
+
+const HEADER_EXTENSIONS: &[&str] = &["h", "hh", "hpp", "hxx"];
+const SOURCE_EXTENSIONS: &[&str] = &["c", "cc", "cpp", "cxx", "mm"];
+
+/// Checks that header edits trigger rebuilds: touches a header the project's
+/// sources include, asks the build tool for a dry run, and warns when nothing
+/// would be recompiled. The header's timestamp is restored afterwards, so
+/// the check itself never causes a rebuild.
+pub fn check(build_dir: &str, generator: &str, config: &str) -> Result<(), std::io::Error> {
+    if generator.starts_with("Visual Studio") || generator == "Xcode" {
+        events::status(format!("{} --depfile-check needs a generator with a dry-run mode (Ninja or Makefiles), skipping it for '{}'", "Warning:".yellow(), generator));
+        return Ok(());
+    }
+    if generator.starts_with("Ninja") && !Path::new(build_dir).join(".ninja_deps").is_file() {
+        events::status(format!("{} {}/.ninja_deps is missing, Ninja has recorded no header dependencies", "Warning:".yellow(), build_dir));
+    }
+
+    let Some((header, source)) = included_header() else {
+        events::status(format!("{} No project header is included by a source file, nothing to check", "Note:".yellow()));
+        return Ok(());
+    };
+    events::status(format!("Checking that editing {} rebuilds {}...", header.display(), source.display()).dimmed());
+
+    let file = fs::File::options().write(true).open(&header)?;
+    let original = file.metadata()?.modified()?;
+    file.set_modified(SystemTime::now())?;
+    let dry_run = planned_compiles(build_dir, generator, config);
+    file.set_modified(original)?;
+    let planned = dry_run?;
+
+    if planned == 0 {
+        events::status(format!("{} Editing {} would not rebuild anything. Header dependencies aren't tracked, so header changes can leave stale objects behind.", "Warning:".yellow(), header.display()));
+        events::status(format!("  {}", "Check that the compiler writes depfiles (-MD/-MMD, or /showIncludes for MSVC) and that the header isn't included from outside the source tree".cyan()));
+    } else {
+        events::status(format!("{} Editing {} rebuilds {} object(s), header dependencies are tracked.", "Success:".green(), header.display(), planned));
+    }
+    Ok(())
+}
+
+/// The first project header (with a source including it by file name), as
+/// `(header, source)`.
+fn included_header() -> Option<(PathBuf, PathBuf)> {
+    let headers = collect_sources(Path::new("."), HEADER_EXTENSIONS);
+    let sources = collect_sources(Path::new("."), SOURCE_EXTENSIONS);
+    let contents: Vec<(PathBuf, String)> = sources.into_iter().filter_map(|source| Some((source.clone(), fs::read_to_string(&source).ok()?))).collect();
+    headers.into_iter().find_map(|header| {
+        let name = header.file_name()?.to_string_lossy().into_owned();
+        let (source, _) = contents.iter().find(|(_, content)| {
+            content.lines().any(|line| {
+                let line = line.trim_start();
+                line.starts_with("#include") && (line.contains(&format!("/{}\"", name)) || line.contains(&format!("\"{}\"", name)))
+            })
+        })?;
+        let relative = |path: &Path| path.strip_prefix(".").unwrap_or(path).to_path_buf();
+        Some((relative(&header), relative(source)))
+    })
+}
+
+/// The number of compile steps a dry run of the native build tool lists.
+fn planned_compiles(build_dir: &str, generator: &str, config: &str) -> Result<usize, std::io::Error> {
+    let mut command = buildenv::tool_command("cmake");
+    command.args(["--build", build_dir]);
+    if cmake::is_multi_config(generator) {
+        command.args(["--config", config]);
+    }
+    let output = command.args(["--", "-n"]).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(std::io::Error::other(format!("Dry-run build failed:\n{}", stderr)));
+    }
+    // Ninja prints "[1/2] Building CXX object ...", Make the compiler commands.
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| line.contains("Building CXX object") || line.contains("Building C object") || line.contains(" -c "))
+        .count())
+}
@@ -0,0 +1,284 @@
+use colored::*;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use crate::version::Version;
+use crate::{dependencies_source, events, project_dependencies, write_conanfile};
+
+/// The resolved-versions snapshot `sage install` keeps up to date once it
+/// exists, and that `sage deps why` answers from while it is current.
+pub const RESOLVED_FILE: &str = "packages/resolved.txt";
+
+/// How a direct requirement of the project is listed among "required by".
+const PROJECT_PARENT: &str = "(project)";
+
+/// Prints every chain from a direct requirement down to `package` in the
+/// resolved Conan graph, the C++ analog of `cargo tree -i`.
+pub fn why(package: &str) -> Result<(), std::io::Error> {
+    let paths = match snapshot_parents() {
+        Some(parents) => {
+            events::status(format!("Using {} (run 'sage install' to refresh it)", RESOLVED_FILE).dimmed());
+            snapshot_paths(&parents, package)
+        }
+        None => dependency_paths(&resolve_graph()?, package),
+    };
+
+    if paths.is_empty() {
+        println!("{} '{}' is not part of the dependency graph.", "Note:".yellow(), package);
+        return Ok(());
+    }
+
+    println!("{} is required by:", package.bold());
+    for path in paths {
+        if path.len() == 1 {
+            println!("  {} {}", path[0], "(direct dependency)".dimmed());
+        } else {
+            println!("  {}", path.join(" -> "));
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists each dependency with the newest version on the Conan remotes and
+/// marks the ones that are behind. Read-only: nothing is installed or
+/// rewritten, `sage bump` does that.
+pub fn outdated() -> Result<(), std::io::Error> {
+    let dependencies = project_dependencies()?;
+    if dependencies.is_empty() {
+        println!("{} No dependencies listed in {}.", "Note:".yellow(), dependencies_source()?);
+        return Ok(());
+    }
+
+    events::status("Checking the Conan remotes for newer versions...".green());
+    let width = dependencies.iter().map(|dep| dep.split('/').next().unwrap_or(dep).len()).max().unwrap_or(0);
+    let mut behind = 0;
+    for dependency in &dependencies {
+        let (name, spec) = dependency.split_once('/').unwrap_or((dependency, ""));
+        let current = spec.split('@').next().unwrap_or(spec);
+        let label = format!("{:<width$}", name, width = width);
+        let latest = match latest_version(name) {
+            Ok(Some(latest)) => latest,
+            Ok(None) => {
+                println!("  {} {:<10} {}", label, current, "not found on any remote".yellow());
+                continue;
+            }
+            Err(e) => {
+                println!("  {} {:<10} {}", label, current, e.to_string().red());
+                continue;
+            }
+        };
+        match Version::parse(current) {
+            Some(version) if version < latest => {
+                behind += 1;
+                let gap = version.gap(&latest);
+                let line = format!("{} {:<10} -> {} ({} behind)", label, current, latest, gap);
+                println!("  {}", if gap == "major" { line.red() } else { line.yellow() });
+            }
+            Some(_) => println!("  {} {:<10} {}", label, current, "up to date".green()),
+            // Version ranges and non-numeric versions (e.g. cci.20230101) can't be compared.
+            None => println!("  {} {:<10} {}", label, current, format!("latest is {}", latest).dimmed()),
+        }
+    }
+
+    if behind == 0 {
+        println!("{} All comparable dependencies are up to date.", "Success:".green());
+    } else {
+        println!("{} of {} dependencies are behind.", behind, dependencies.len());
+        println!("  {}", "Upgrade one with: sage bump <name> --to <version>".cyan());
+    }
+    Ok(())
+}
+
+/// The newest numeric version of `name` across the configured Conan
+/// remotes, from `conan search <name> --format=json`.
+pub fn latest_version(name: &str) -> Result<Option<Version>, std::io::Error> {
+    let output = Command::new("conan").args(["search", name, "--format=json"]).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(std::io::Error::other(format!("conan search failed: {}", stderr.trim())));
+    }
+    let results: Value = serde_json::from_slice(&output.stdout)
+        .map_err(|e| std::io::Error::other(format!("Could not parse Conan search output: {}", e)))?;
+
+    // {"<remote>": {"<name>/<version>": {}, ...}, ...}; remotes that failed
+    // or don't have the package report {"error": "..."} instead.
+    let prefix = format!("{}/", name);
+    Ok(results
+        .as_object()
+        .into_iter()
+        .flat_map(|remotes| remotes.values())
+        .filter_map(Value::as_object)
+        .flat_map(|references| references.keys())
+        .filter_map(|reference| reference.strip_prefix(&prefix))
+        .filter_map(|version| Version::parse(version.split('@').next().unwrap_or(version)))
+        .max())
+}
+
+/// Runs `conan graph info` against the project's requirements and returns the
+/// parsed JSON graph.
+fn resolve_graph() -> Result<Value, std::io::Error> {
+    let dependencies = project_dependencies()?;
+    if dependencies.is_empty() {
+        return Err(std::io::Error::other(format!("No dependencies listed in {}.", dependencies_source()?)));
+    }
+
+    let conanfile_path = Path::new("conanfile.txt");
+    write_conanfile(conanfile_path, &dependencies, &[])?;
+
+    events::status("Resolving dependency graph with Conan...".green());
+    let output = Command::new("conan")
+        .args(["graph", "info", ".", "--format=json"])
+        .output();
+
+    fs::remove_file(conanfile_path)?;
+    let output = output?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(std::io::Error::other(format!("Conan graph info failed:\n{}", stderr)));
+    }
+
+    serde_json::from_slice(&output.stdout)
+        .map_err(|e| std::io::Error::other(format!("Could not parse Conan graph output: {}", e)))
+}
+
+/// Collects the reference chains (e.g. `["boost/1.84.0", "zlib/1.3.1"]`) that
+/// lead from the root conanfile's direct requirements to `package`.
+fn dependency_paths(graph: &Value, package: &str) -> Vec<Vec<String>> {
+    let mut paths = Vec::new();
+    if let Some(nodes) = graph["graph"]["nodes"].as_object() {
+        let mut current = Vec::new();
+        collect_paths(nodes, "0", package, &mut current, &mut paths);
+    }
+    paths
+}
+
+fn collect_paths(
+    nodes: &serde_json::Map<String, Value>,
+    node_id: &str,
+    package: &str,
+    current: &mut Vec<String>,
+    paths: &mut Vec<Vec<String>>,
+) {
+    let Some(dependencies) = nodes.get(node_id).and_then(|node| node["dependencies"].as_object()) else {
+        return;
+    };
+
+    // Conan lists transitive requirements on every node too; only follow the
+    // direct edges so each chain reflects who actually requires what.
+    for (child_id, edge) in dependencies {
+        if !edge["direct"].as_bool().unwrap_or(false) {
+            continue;
+        }
+        let Some(child) = nodes.get(child_id) else {
+            continue;
+        };
+        let reference = display_ref(child);
+        if current.contains(&reference) {
+            continue;
+        }
+
+        current.push(reference);
+        if node_name(child) == package {
+            paths.push(current.clone());
+        } else {
+            collect_paths(nodes, child_id, package, current, paths);
+        }
+        current.pop();
+    }
+}
+
+fn node_name(node: &Value) -> String {
+    match node["name"].as_str() {
+        Some(name) => name.to_string(),
+        None => display_ref(node).split('/').next().unwrap_or("").to_string(),
+    }
+}
+
+/// The node's reference without the recipe revision, e.g. `zlib/1.3.1`.
+fn display_ref(node: &Value) -> String {
+    let reference = node["ref"].as_str().unwrap_or("");
+    reference.split('#').next().unwrap_or(reference).to_string()
+}
+
+/// Writes the exact versions Conan resolved to `path`, one reference per line
+/// with the packages requiring it, e.g. `zlib/1.3.1  <- openssl/3.2.0`.
+pub fn write_snapshot(graph: &Value, path: &Path) -> Result<usize, std::io::Error> {
+    let mut parents: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    if let Some(nodes) = graph["graph"]["nodes"].as_object() {
+        for (node_id, node) in nodes {
+            let Some(dependencies) = node["dependencies"].as_object() else {
+                continue;
+            };
+            let parent = if node_id == "0" { PROJECT_PARENT.to_string() } else { display_ref(node) };
+            for (child_id, edge) in dependencies {
+                if !edge["direct"].as_bool().unwrap_or(false) {
+                    continue;
+                }
+                if let Some(child) = nodes.get(child_id) {
+                    parents.entry(display_ref(child)).or_default().insert(parent.clone());
+                }
+            }
+        }
+    }
+
+    let mut content = String::from("# Dependency versions resolved by `sage install`. Do not edit.\n# <reference>  <- <required by>\n");
+    for (reference, required_by) in &parents {
+        let required_by: Vec<&str> = required_by.iter().map(String::as_str).collect();
+        content.push_str(&format!("{}  <- {}\n", reference, required_by.join(", ")));
+    }
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)?;
+    Ok(parents.len())
+}
+
+/// Reads the snapshot's "required by" edges, or `None` when there is no
+/// snapshot or the requirements changed after it was written.
+fn snapshot_parents() -> Option<BTreeMap<String, Vec<String>>> {
+    let modified = |path: &str| fs::metadata(path).and_then(|m| m.modified()).ok();
+    if modified(RESOLVED_FILE)? < modified(dependencies_source().ok()?)? {
+        return None;
+    }
+    let content = fs::read_to_string(RESOLVED_FILE).ok()?;
+    let parents = content
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (reference, required_by) = line.split_once("<-")?;
+            Some((reference.trim().to_string(), required_by.split(',').map(|p| p.trim().to_string()).collect()))
+        })
+        .collect();
+    Some(parents)
+}
+
+/// The chains from direct requirements down to `package`, walked upwards
+/// through the snapshot's "required by" edges.
+fn snapshot_paths(parents: &BTreeMap<String, Vec<String>>, package: &str) -> Vec<Vec<String>> {
+    let mut paths = Vec::new();
+    for reference in parents.keys().filter(|reference| reference.split('/').next() == Some(package)) {
+        let mut current = vec![reference.clone()];
+        collect_parent_paths(parents, &mut current, &mut paths);
+    }
+    paths
+}
+
+fn collect_parent_paths(parents: &BTreeMap<String, Vec<String>>, current: &mut Vec<String>, paths: &mut Vec<Vec<String>>) {
+    let Some(required_by) = current.last().and_then(|reference| parents.get(reference)) else {
+        return;
+    };
+    for parent in required_by {
+        if parent == PROJECT_PARENT {
+            paths.push(current.iter().rev().cloned().collect());
+        } else if !current.contains(parent) {
+            current.push(parent.clone());
+            collect_parent_paths(parents, current, paths);
+            current.pop();
+        }
+    }
+}
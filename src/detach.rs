@@ -0,0 +1,106 @@
+use colored::*;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use crate::events;
+
+/// Where `sage run --detach` records the PID of the process it started.
+const PID_FILE: &str = "build/.sage-detached.pid";
+/// Where the detached process's stdout and stderr go.
+const LOG_FILE: &str = "build/detached.log";
+
+/// Starts `command` in the background without waiting for it, recording its
+/// PID so `sage stop` can end it later.
+pub fn spawn_detached(command: &mut Command) -> Result<(), std::io::Error> {
+    if let Some(pid) = running_pid() {
+        return Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, format!("A detached process (PID {}) is already running. Stop it with 'sage stop'.", pid)));
+    }
+
+    let log = fs::File::create(LOG_FILE)?;
+    command.stdout(log.try_clone()?).stderr(log);
+    detach_from_terminal(command);
+    let child = command.spawn()?;
+
+    fs::write(PID_FILE, child.id().to_string())?;
+    events::status(format!("{} Started in the background with PID {}", "Success:".green(), child.id().to_string().bold()));
+    events::status(format!("  Output goes to {}. Stop it with: {}", LOG_FILE, "sage stop".cyan()));
+    Ok(())
+}
+
+/// Stops the process started by the last `sage run --detach`.
+pub fn stop() -> Result<(), std::io::Error> {
+    let Some(pid) = recorded_pid() else {
+        events::status(format!("{} No detached process was started from this project.", "Note:".yellow()));
+        return Ok(());
+    };
+    if !is_running(pid) {
+        fs::remove_file(PID_FILE)?;
+        events::status(format!("{} The detached process (PID {}) has already exited.", "Note:".yellow(), pid));
+        return Ok(());
+    }
+
+    let output = kill_command(pid).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(std::io::Error::other(format!("Could not stop PID {}:\n{}", pid, stderr)));
+    }
+    fs::remove_file(PID_FILE)?;
+    events::status(format!("{} Stopped the detached process (PID {})", "Success:".green(), pid));
+    Ok(())
+}
+
+fn recorded_pid() -> Option<u32> {
+    fs::read_to_string(Path::new(PID_FILE)).ok()?.trim().parse().ok()
+}
+
+fn running_pid() -> Option<u32> {
+    recorded_pid().filter(|pid| is_running(*pid))
+}
+
+/// Puts the child in its own process group (Unix) or console-less process
+/// group (Windows), so Ctrl+C in sage's terminal doesn't reach it.
+#[cfg(unix)]
+fn detach_from_terminal(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    command.process_group(0);
+}
+
+#[cfg(windows)]
+fn detach_from_terminal(command: &mut Command) {
+    use std::os::windows::process::CommandExt;
+    const DETACHED_PROCESS: u32 = 0x0000_0008;
+    const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+    command.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+#[cfg(windows)]
+fn is_running(pid: u32) -> bool {
+    Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()))
+}
+
+#[cfg(unix)]
+fn kill_command(pid: u32) -> Command {
+    let mut command = Command::new("kill");
+    command.arg(pid.to_string());
+    command
+}
+
+#[cfg(windows)]
+fn kill_command(pid: u32) -> Command {
+    let mut command = Command::new("taskkill");
+    command.args(["/PID", &pid.to_string(), "/F"]);
+    command
+}
@@ -0,0 +1,57 @@
+use colored::*;
+use std::path::Path;
+
+use crate::{events, write_if_missing, REQUIRED_TOOLS};
+
+/// Base image used when `--base` isn't given.
+pub const DEFAULT_BASE_IMAGE: &str = "ubuntu:24.04";
+
+/// Writes a `Dockerfile` (and a matching `.dockerignore`) to `root` that
+/// installs the tools `sage doctor` checks for, then installs the project's
+/// dependencies and compiles it.
+pub fn write_dockerfile(root: &Path, project_name: &str, base: &str) -> Result<(), std::io::Error> {
+    let image = base.rsplit('/').next().unwrap_or(base);
+    if !image.starts_with("ubuntu") && !image.starts_with("debian") {
+        events::status(format!("{} The generated Dockerfile uses apt-get, which '{}' may not provide", "Warning:".yellow(), base));
+    }
+
+    write_if_missing(&root.join("Dockerfile"), &dockerfile(project_name, base))?;
+    write_if_missing(&root.join(".dockerignore"), DOCKERIGNORE_CONTENT)
+}
+
+fn dockerfile(project_name: &str, base: &str) -> String {
+    let apt_packages: Vec<&str> = REQUIRED_TOOLS.iter().filter_map(|tool| tool.apt_package).collect();
+    format!(r#"# Builds the project in a clean container: docker build -t {0} .
+ARG BASE_IMAGE={1}
+
+# A static musl binary runs on any base image, whatever its glibc version.
+FROM rust:1 AS sage
+RUN target="$(uname -m)-unknown-linux-musl" \
+    && rustup target add "$target" \
+    && cargo install --git https://github.com/vishal-ahirwar/cppsage --root /opt/sage --target "$target"
+
+FROM ${{BASE_IMAGE}}
+ENV DEBIAN_FRONTEND=noninteractive
+RUN apt-get update \
+    && apt-get install -y --no-install-recommends build-essential ca-certificates git python3 python3-venv {2} \
+    && rm -rf /var/lib/apt/lists/*
+RUN python3 -m venv /opt/conan && /opt/conan/bin/pip install --no-cache-dir conan
+ENV PATH=/opt/conan/bin:$PATH
+RUN conan profile detect
+
+COPY --from=sage /opt/sage/bin/cppsage /usr/local/bin/cppsage
+
+# sage takes the project name from the directory name. install always
+# generates the Conan toolchain compile needs, even without dependencies,
+# and either failing fails the image build.
+WORKDIR /src/{0}
+COPY . .
+RUN cppsage install && cppsage compile
+"#, project_name, base, apt_packages.join(" "))
+}
+
+const DOCKERIGNORE_CONTENT: &str = r#".git/
+build/
+packages/install/
+packages/install-*/
+"#;
@@ -0,0 +1,55 @@
+use std::fs;
+use std::path::Path;
+
+/// Reads the `KEY=VALUE` lines of a dotenv file, in order. Blank lines and
+/// `#` comments are skipped, an `export ` prefix is allowed, and values may
+/// be single-quoted (taken literally) or double-quoted (with `\n`, `\"` and
+/// `\\` escapes). Unquoted values end at a ` #` comment.
+pub fn load(path: &Path) -> Result<Vec<(String, String)>, std::io::Error> {
+    if !path.is_file() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("env file not found: {}", path.display())));
+    }
+    let content = fs::read_to_string(path)?;
+    let mut vars = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let invalid = |problem: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}:{}: {}", path.display(), index + 1, problem));
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(invalid("expected KEY=VALUE"));
+        };
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') || key.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(invalid(&format!("'{}' is not a valid variable name", key)));
+        }
+        vars.push((key.to_string(), parse_value(value.trim()).ok_or_else(|| invalid("unterminated quoted value"))?));
+    }
+    Ok(vars)
+}
+
+fn parse_value(value: &str) -> Option<String> {
+    if let Some(rest) = value.strip_prefix('\'') {
+        return rest.find('\'').map(|end| rest[..end].to_string());
+    }
+    if let Some(rest) = value.strip_prefix('"') {
+        let mut parsed = String::new();
+        let mut chars = rest.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => return Some(parsed),
+                '\\' => match chars.next()? {
+                    'n' => parsed.push('\n'),
+                    't' => parsed.push('\t'),
+                    other => parsed.push(other),
+                },
+                c => parsed.push(c),
+            }
+        }
+        return None;
+    }
+    let value = value.split_once(" #").map_or(value, |(value, _)| value);
+    Some(value.trim_end().to_string())
+}
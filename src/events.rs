@@ -0,0 +1,149 @@
+use clap::ValueEnum;
+use colored::*;
+use regex::Regex;
+use serde_json::{json, Value};
+use std::fmt::Display;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::OnceLock;
+
+#[derive(Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum MessageFormat {
+    /// Colored, human-readable output
+    #[default]
+    Human,
+    /// Line-delimited JSON events on stdout
+    Json,
+}
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Switches the process to JSON output: from now on stdout only carries
+/// events, and human-readable status lines go to stderr.
+pub fn enable_json() {
+    JSON.store(true, Ordering::Relaxed);
+}
+
+pub fn json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// How much sage prints besides errors, set once from `--quiet`/`--verbose`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Verbosity {
+    /// Errors only
+    Quiet,
+    Normal,
+    /// Also the commands sage runs
+    Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn quiet() -> bool {
+    VERBOSITY.load(Ordering::Relaxed) == Verbosity::Quiet as u8
+}
+
+pub fn verbose() -> bool {
+    VERBOSITY.load(Ordering::Relaxed) == Verbosity::Verbose as u8
+}
+
+/// Whether raw tool output (CMake, Conan, the compiler) should be echoed
+/// after a successful step.
+pub fn show_tool_output(summary_only: bool) -> bool {
+    !summary_only && !json() && !quiet()
+}
+
+/// Prints `command` as a shell would show it, in verbose mode only.
+pub fn command(command: &Command) {
+    if !verbose() {
+        return;
+    }
+    let words: Vec<String> = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|word| {
+            let word = word.to_string_lossy();
+            if word.is_empty() || word.contains(char::is_whitespace) {
+                format!("\"{}\"", word)
+            } else {
+                word.into_owned()
+            }
+        })
+        .collect();
+    status(format!("$ {}", words.join(" ")).dimmed());
+}
+
+/// Prints a human-readable status line, keeping stdout clean in JSON mode.
+/// Quiet mode drops it.
+pub fn status(line: impl Display) {
+    if quiet() {
+        return;
+    }
+    if json() {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// Emits one `{"reason": ..., ...}` event line when JSON output is enabled.
+pub fn emit(reason: &str, fields: Value) {
+    if !json() {
+        return;
+    }
+    let mut event = json!({ "reason": reason });
+    if let (Some(event), Value::Object(fields)) = (event.as_object_mut(), fields) {
+        event.extend(fields);
+    }
+    println!("{}", event);
+}
+
+/// Emits `compile-progress` events for Ninja's `[n/total]` lines and
+/// `diagnostic` events for GCC/Clang/MSVC diagnostics found in a build log.
+pub fn emit_build_log(log: &str) {
+    if !json() {
+        return;
+    }
+    for line in log.lines() {
+        if let Some(progress) = progress_regex().captures(line) {
+            emit("compile-progress", json!({
+                "current": progress[1].parse::<u64>().unwrap_or(0),
+                "total": progress[2].parse::<u64>().unwrap_or(0),
+                "message": progress[3].trim(),
+            }));
+        } else if let Some(diagnostic) = gnu_diagnostic_regex().captures(line).or_else(|| msvc_diagnostic_regex().captures(line)) {
+            emit("diagnostic", json!({
+                "level": diagnostic["level"].replace("fatal ", ""),
+                "file": &diagnostic["file"],
+                "line": diagnostic["line"].parse::<u64>().unwrap_or(0),
+                "column": diagnostic.name("column").and_then(|c| c.as_str().parse::<u64>().ok()),
+                "message": diagnostic["message"].trim(),
+            }));
+        }
+    }
+}
+
+fn progress_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"^\[(\d+)/(\d+)\]\s*(.*)$").unwrap())
+}
+
+/// `file:line[:column]: error: message`, as printed by GCC and Clang.
+fn gnu_diagnostic_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"^(?P<file>(?:[A-Za-z]:)?[^:]+):(?P<line>\d+):(?:(?P<column>\d+):)?\s*(?P<level>fatal error|error|warning|note):\s*(?P<message>.*)$").unwrap()
+    })
+}
+
+/// `file(line[,column]): error C2065: message`, as printed by MSVC.
+fn msvc_diagnostic_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"^(?P<file>.+?)\((?P<line>\d+)(?:,(?P<column>\d+))?\)\s*:\s*(?P<level>fatal error|error|warning|note)(?:\s+[A-Z]+\d+)?\s*:\s*(?P<message>.*)$").unwrap()
+    })
+}
@@ -0,0 +1,111 @@
+use clap::Args;
+use colored::*;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{collect_sources, events};
+
+/// Sources and headers clang-format is run over.
+pub const FORMAT_EXTENSIONS: &[&str] = &["c", "cc", "cpp", "cxx", "mm", "h", "hh", "hpp", "hxx", "ipp", "inl"];
+
+#[derive(Args)]
+pub struct FormatArgs {
+    /// Report files that need formatting instead of rewriting them
+    #[arg(long)]
+    check: bool,
+    /// Only format files staged in git
+    #[arg(long, conflicts_with = "changed")]
+    staged: bool,
+    /// Only format files changed in git since the last commit, staged or not, and untracked ones
+    #[arg(long)]
+    changed: bool,
+    /// Format source read from stdin and write the result to stdout (for editors)
+    #[arg(long, conflicts_with_all = ["check", "staged", "changed"])]
+    pub stdin: bool,
+    /// The path the stdin source belongs to; picks the language and the .clang-format
+    #[arg(long, value_name = "PATH", requires = "stdin")]
+    assume_filename: Option<PathBuf>,
+}
+
+pub fn run_format(args: &FormatArgs) -> Result<(), std::io::Error> {
+    if args.stdin {
+        return format_stdin(args.assume_filename.as_deref());
+    }
+    let mut sources = collect_sources(Path::new("."), FORMAT_EXTENSIONS);
+
+    if args.staged || args.changed {
+        match git_changed_files(args.staged) {
+            Some(changed) => sources.retain(|source| changed.contains(source)),
+            None => events::status(format!("{} Not in a git repository, formatting every file.", "Note:".yellow())),
+        }
+    }
+
+    if sources.is_empty() {
+        events::status("No files to format.".yellow());
+        return Ok(());
+    }
+
+    events::status(format!("{} {} file(s) with clang-format...", if args.check { "Checking" } else { "Formatting" }.green(), sources.len()));
+    let mut command = Command::new("clang-format");
+    if args.check {
+        command.args(["--dry-run", "--Werror"]);
+    } else {
+        command.arg("-i");
+    }
+    let output = command.args(&sources).output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => std::io::Error::new(e.kind(), "clang-format not found in PATH. Install LLVM or run 'sage doctor'."),
+        _ => e,
+    })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if args.check {
+            eprintln!("{}", stderr);
+            return Err(std::io::Error::other("Some files are not formatted. Run 'sage fmt' to fix them."));
+        }
+        return Err(std::io::Error::other(format!("clang-format failed:\n{}", stderr)));
+    }
+
+    events::status(format!("{} {}", "Success:".green(), if args.check { "All files are formatted." } else { "Files formatted." }));
+    Ok(())
+}
+
+/// Pipes stdin through clang-format to stdout, for editors that format on
+/// save. clang-format looks up `.clang-format` from `assume_filename`'s
+/// directory (resolved against the current directory), so the project's
+/// style applies. Prints nothing of its own, and exits with clang-format's
+/// code when it fails; its message is already on stderr.
+fn format_stdin(assume_filename: Option<&Path>) -> Result<(), std::io::Error> {
+    let mut command = Command::new("clang-format");
+    command.arg("--style=file");
+    if let Some(path) = assume_filename {
+        command.arg(format!("--assume-filename={}", path.display()));
+    }
+    let status = command.status().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => std::io::Error::new(e.kind(), "clang-format not found in PATH. Install LLVM or run 'sage doctor'."),
+        _ => e,
+    })?;
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+/// Lists the files git reports as staged, or with `staged` false every file
+/// changed since `HEAD` plus the untracked ones, relative to the current
+/// directory, or `None` when git can't tell us. Before the first commit
+/// there is no `HEAD`, so the staged files stand in for the changes.
+fn git_changed_files(staged: bool) -> Option<Vec<PathBuf>> {
+    let has_head = Command::new("git").args(["rev-parse", "--verify", "--quiet", "HEAD"]).output().is_ok_and(|output| output.status.success());
+    let mut files = git_file_list(&["diff", "--name-only", "--relative", "--diff-filter=ACMR", if staged || !has_head { "--cached" } else { "HEAD" }])?;
+    if !staged {
+        files.extend(git_file_list(&["ls-files", "--others", "--exclude-standard"])?);
+    }
+    Some(files)
+}
+
+/// Runs a git command printing one path per line.
+fn git_file_list(args: &[&str]) -> Option<Vec<PathBuf>> {
+    let output = Command::new("git").args(args).output().ok().filter(|output| output.status.success())?;
+    Some(String::from_utf8_lossy(&output.stdout).lines().map(|line| Path::new(".").join(line)).collect())
+}
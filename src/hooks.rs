@@ -0,0 +1,63 @@
+use colored::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::events;
+
+const PRE_COMMIT_HOOK: &str = r#"#!/bin/sh
+# Installed by cppsage (sage hooks install): refuse commits with unformatted sources.
+exec cppsage fmt --check
+"#;
+
+const PRE_PUSH_HOOK: &str = r#"#!/bin/sh
+# Installed by cppsage (sage hooks install): build and test before pushing.
+set -e
+cppsage compile
+cppsage test
+"#;
+
+/// Installs the `pre-commit` (format check) and `pre-push` (compile and
+/// test) hooks into the git repository containing `dir`. Hooks that already
+/// exist are left alone, whoever wrote them.
+pub fn install(dir: &Path) -> Result<(), std::io::Error> {
+    let hooks_dir = hooks_dir(dir)?;
+    fs::create_dir_all(&hooks_dir)?;
+    for (name, content) in [("pre-commit", PRE_COMMIT_HOOK), ("pre-push", PRE_PUSH_HOOK)] {
+        let path = hooks_dir.join(name);
+        if path.exists() {
+            events::status(format!("{} {} already exists, skipping", "Note:".yellow(), path.display()));
+            continue;
+        }
+        fs::write(&path, content)?;
+        make_executable(&path)?;
+        events::status(format!("{} Installed the {} hook", "Success:".green(), name));
+    }
+    Ok(())
+}
+
+/// The repository's hooks directory, honouring `core.hooksPath`.
+fn hooks_dir(dir: &Path) -> Result<PathBuf, std::io::Error> {
+    let output = Command::new("git").current_dir(dir).args(["rev-parse", "--git-path", "hooks"]).output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => std::io::Error::new(e.kind(), "git not found in PATH."),
+        _ => e,
+    })?;
+    if !output.status.success() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "Not inside a git repository. Run 'git init' first."));
+    }
+    // Relative to `dir` unless core.hooksPath is absolute.
+    let hooks_dir = dir.join(String::from_utf8_lossy(&output.stdout).trim());
+    Ok(hooks_dir.strip_prefix(".").map(Path::to_path_buf).unwrap_or(hooks_dir))
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<(), std::io::Error> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+}
+
+/// Git for Windows runs hooks through its bundled sh, no mode bits needed.
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<(), std::io::Error> {
+    Ok(())
+}
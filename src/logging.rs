@@ -0,0 +1,118 @@
+use clap::ValueEnum;
+
+/// The logging libraries `sage new --with-logging` knows how to wire up.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Library {
+    /// spdlog, with a colored console logger as the default logger
+    Spdlog,
+    /// fmt, with small leveled print helpers on top of fmt::print
+    Fmt,
+}
+
+impl Library {
+    /// The Conan reference added to `packages/requirements.txt`.
+    pub fn requirement(self) -> &'static str {
+        match self {
+            Library::Spdlog => "spdlog/1.14.1",
+            Library::Fmt => "fmt/10.2.1",
+        }
+    }
+
+    /// The `log.hpp` helper generated into the project's include directory.
+    pub fn header(self, project_name: &str) -> String {
+        match self {
+            Library::Spdlog => format!(r#"#pragma once
+
+#include <spdlog/sinks/stdout_color_sinks.h>
+#include <spdlog/spdlog.h>
+
+namespace logging {{
+
+// Installs a colored console logger as spdlog's default logger, so the
+// spdlog::info/warn/error free functions can be used everywhere.
+inline void init(spdlog::level::level_enum level = spdlog::level::info) {{
+    auto logger = spdlog::stdout_color_mt("{0}");
+    logger->set_pattern("[%H:%M:%S.%e] [%^%l%$] %v");
+    spdlog::set_default_logger(logger);
+    spdlog::set_level(level);
+}}
+
+}} // namespace logging
+"#, project_name),
+            Library::Fmt => FMT_LOG_HPP.to_string(),
+        }
+    }
+
+    /// The `main.cpp` showing the helper in use.
+    pub fn main_cpp(self) -> &'static str {
+        match self {
+            Library::Spdlog => SPDLOG_MAIN_CPP,
+            Library::Fmt => FMT_MAIN_CPP,
+        }
+    }
+}
+
+const FMT_LOG_HPP: &str = r#"#pragma once
+
+#include <fmt/chrono.h>
+#include <fmt/color.h>
+#include <fmt/core.h>
+
+#include <cstdio>
+#include <ctime>
+#include <utility>
+
+namespace logging {
+
+enum class Level { Debug, Info, Warn, Error };
+
+template <typename... Args>
+void log(Level level, fmt::format_string<Args...> format, Args&&... args) {
+    static constexpr const char* names[] = {"debug", "info", "warn", "error"};
+    static constexpr fmt::color colors[] = {fmt::color::gray, fmt::color::green, fmt::color::yellow, fmt::color::red};
+    const auto index = static_cast<int>(level);
+    fmt::print(stderr, "[{:%H:%M:%S}] [{}] {}\n", fmt::localtime(std::time(nullptr)),
+               fmt::styled(names[index], fmt::fg(colors[index])), fmt::format(format, std::forward<Args>(args)...));
+}
+
+template <typename... Args>
+void debug(fmt::format_string<Args...> format, Args&&... args) {
+    log(Level::Debug, format, std::forward<Args>(args)...);
+}
+
+template <typename... Args>
+void info(fmt::format_string<Args...> format, Args&&... args) {
+    log(Level::Info, format, std::forward<Args>(args)...);
+}
+
+template <typename... Args>
+void warn(fmt::format_string<Args...> format, Args&&... args) {
+    log(Level::Warn, format, std::forward<Args>(args)...);
+}
+
+template <typename... Args>
+void error(fmt::format_string<Args...> format, Args&&... args) {
+    log(Level::Error, format, std::forward<Args>(args)...);
+}
+
+} // namespace logging
+"#;
+
+const SPDLOG_MAIN_CPP: &str = r#"#include "log.hpp"
+
+int main() {
+    logging::init();
+    spdlog::info("Hello, {}!", "world");
+    spdlog::debug("Hidden unless logging::init(spdlog::level::debug) is used");
+    return 0;
+}
+"#;
+
+const FMT_MAIN_CPP: &str = r#"#include "log.hpp"
+
+int main() {
+    logging::info("Hello, {}!", "world");
+    logging::warn("{} warning(s) so far", 1);
+    return 0;
+}
+"#;
@@ -1,15 +1,54 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
 use colored::*;
 use std::fs;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
-use std::process::Command;
+use std::io::{BufRead, BufReader, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode, Stdio};
 use std::env;
+use std::ffi::OsString;
+use std::sync::OnceLock;
+
+mod backend;
+mod bootstrap;
+mod buildenv;
+mod cmake;
+mod compiler;
+mod cross;
+mod coredump;
+mod debugger;
+mod depfile;
+mod deps;
+mod detach;
+mod docker;
+mod dotenv;
+mod events;
+mod format;
+mod hooks;
+mod logging;
+mod manifest;
+mod pgo;
+mod reproducible;
+mod sanitize;
+mod targets;
+mod terminal;
+mod tidy;
+mod timetrace;
+mod todos;
+mod version;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 #[command(propagate_version = true)]
 struct Cli {
+    /// Only print errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Also print the commands sage runs (and the output of failing tests)
+    #[arg(short, long, global = true)]
+    verbose: bool,
+    /// Never color the output (also set by NO_COLOR; off automatically when not writing to a terminal)
+    #[arg(long, global = true)]
+    no_color: bool,
     #[command(subcommand)]
     command: Commands,
 }
@@ -17,281 +56,3001 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Create a new C++ project
-    New {
-        /// The name of the project
-        #[arg(required = true)]
-        name: String,
+    New(NewArgs),
+    /// Add the cppsage project structure to the current directory, named after it
+    Init {
+        /// Overwrite existing project files (sources are never overwritten)
+        #[arg(long)]
+        force: bool,
     },
     /// Install dependencies
-    Install,
+    Install(InstallArgs),
     /// Compile the project
-    Compile,
+    Compile(CompileArgs),
     /// Compile and run the project
-    Run,
-    /// Debug the project
-    Debug,
+    Run(RunArgs),
+    /// Stop the program started by `sage run --detach`
+    Stop,
+    /// Remove build artifacts
+    Clean(CleanArgs),
+    /// Compile the project and run its CTest tests
+    Test(TestArgs),
+    /// Compile a debug build and launch it under gdb, lldb or cdb
+    Debug(DebugArgs),
     /// Check for required tools
-    Doctor,
+    Doctor {
+        /// Also build and run a throwaway hello-world project end-to-end
+        #[arg(long)]
+        deep: bool,
+        /// Offer to install missing required tools (asking first)
+        #[arg(long)]
+        fix: bool,
+        /// Print the required tool checks as JSON instead of colored text
+        #[arg(long, conflicts_with_all = ["deep", "fix"])]
+        json: bool,
+    },
+    /// Run clang-tidy over the project's sources
+    Tidy(tidy::TidyArgs),
+    /// Format the project's sources with clang-format
+    #[command(alias = "format")]
+    Fmt(format::FormatArgs),
+    /// Build twice in clean directories and compare the executables' hashes
+    VerifyReproducible(CompileArgs),
+    /// List the configured project's buildable targets
+    Targets,
+    /// Validate the sage.toml manifest
+    CheckConfig,
+    /// Generate a Dockerfile that installs the toolchain and builds the project
+    Dockerfile {
+        /// The base image (must provide apt-get)
+        #[arg(long, value_name = "IMAGE", default_value = docker::DEFAULT_BASE_IMAGE)]
+        base: String,
+    },
+    /// Add a Conan dependency (e.g. fmt/10.2.1) to packages/requirements.txt
+    Add {
+        /// The package reference, as name/version
+        package: String,
+        /// Run `sage install` afterwards
+        #[arg(long)]
+        install: bool,
+    },
+    /// Move one dependency in packages/requirements.txt to another version and reinstall
+    Bump {
+        /// The package name, e.g. fmt
+        package: String,
+        /// The version to move to, e.g. 11.0.0
+        #[arg(long, value_name = "VERSION")]
+        to: String,
+    },
+    /// Move every pinned dependency in packages/requirements.txt to its latest version and reinstall
+    Update {
+        /// Only print the proposed changes, without writing or installing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Remove a dependency from packages/requirements.txt and the CMakeLists
+    Remove {
+        /// The package name (any version matches)
+        package: String,
+    },
+    /// Add a source file to a target's source list in CMakeLists.txt
+    AddSource {
+        /// The source file, relative to the project root
+        file: PathBuf,
+        /// The target to add it to (defaults to the project's main target)
+        #[arg(long)]
+        target: Option<String>,
+    },
+    /// Inspect the project's dependency graph
+    Deps {
+        #[command(subcommand)]
+        command: DepsCommands,
+    },
+    /// Manage the project's .gitignore
+    Gitignore {
+        #[command(subcommand)]
+        command: GitignoreCommands,
+    },
+    /// Manage the project's git hooks
+    Hooks {
+        #[command(subcommand)]
+        command: HooksCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum HooksCommands {
+    /// Install the pre-commit (fmt --check) and pre-push (compile, test) hooks, skipping existing ones
+    Install,
+}
+
+#[derive(Subcommand)]
+enum GitignoreCommands {
+    /// Append patterns to .gitignore, skipping ones already listed
+    Add {
+        /// The patterns to ignore, e.g. "*.bin" "data/cache/"
+        #[arg(required = true)]
+        patterns: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum DepsCommands {
+    /// Show which direct dependencies pull in a package, like `cargo tree -i`
+    Why {
+        /// The package to trace, e.g. zlib
+        package: String,
+    },
+    /// List dependencies with newer versions on the Conan remotes (read-only)
+    Outdated,
+}
+
+#[derive(Args)]
+struct NewArgs {
+    /// The name of the project
+    #[arg(required = true)]
+    name: String,
+    /// Generate a .pre-commit-config.yaml running cppsage's format/tidy hooks
+    #[arg(long)]
+    pre_commit: bool,
+    /// The build system to scaffold
+    #[arg(long, value_enum, default_value_t = BuildSystem::Cmake)]
+    build_system: BuildSystem,
+    /// Scaffold a library (add_library) instead of an executable
+    #[arg(long)]
+    lib: bool,
+    /// The style of the generated CMakeLists.txt files
+    #[arg(long, value_enum, default_value_t = CmakeStyle::Classic)]
+    cmake_style: CmakeStyle,
+    /// The languages to enable in the top-level project(): C++, or C++ plus Objective-C++ (macOS)
+    #[arg(long, value_enum, default_value_t = Language::Cxx)]
+    lang: Language,
+    /// The C++ standard to compile with: 11, 14, 17, 20 or 23
+    #[arg(long = "std", value_name = "VERSION", default_value = "17", value_parser = parse_cpp_std)]
+    cpp_std: u32,
+    /// Scaffold a library with the bulk of the code plus a thin app/main.cpp executable linking it
+    #[arg(long, conflicts_with = "lib")]
+    split: bool,
+    /// Accepted for compatibility: --lib now generates install/export rules by default
+    #[arg(long, requires = "lib", hide = true)]
+    installable: bool,
+    /// With --lib, skip the install/export rules that make the library find_package-able
+    #[arg(long, requires = "lib", conflicts_with = "installable")]
+    no_install_rules: bool,
+    /// Pre-wire a logging library: add it to requirements.txt, link it and generate include/log.hpp
+    #[arg(long, value_enum, value_name = "LIBRARY", conflicts_with_all = ["lib", "split"])]
+    with_logging: Option<logging::Library>,
+    /// Directory for the target's sources, relative to <name>/ ("." for a flat layout)
+    #[arg(long, value_name = "DIR", default_value = "src")]
+    source_dir: String,
+    /// Directory for the target's headers, relative to <name>/ ("." for a flat layout)
+    #[arg(long, value_name = "DIR", default_value = "include")]
+    include_dir: String,
+    /// Also generate a Dockerfile that builds the project (see `sage dockerfile`)
+    #[arg(long)]
+    docker: bool,
+    /// Extra pattern for the generated .gitignore (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    git_ignore_extra: Vec<String>,
+    /// Pin the Conan profile `sage install` uses, recorded in sage.toml
+    #[arg(long, value_name = "PROFILE")]
+    profile_conan: Option<String>,
+    /// Delete and recreate the project if its directory already exists (asks first)
+    #[arg(long)]
+    overwrite: bool,
+    /// Don't ask before deleting the existing project
+    #[arg(long, requires = "overwrite")]
+    yes: bool,
+    /// Skip the editor and formatting configs (.clang-format, .clang-tidy, .clangd, .editorconfig, .gitignore)
+    #[arg(long)]
+    minimal: bool,
+    /// Initialize a git repository with an initial commit (the default)
+    #[arg(long, overrides_with = "no_git", hide = true)]
+    git: bool,
+    /// Don't initialize a git repository
+    #[arg(long)]
+    no_git: bool,
+    /// Install git hooks: pre-commit runs `fmt --check`, pre-push runs `compile` and `test`
+    #[arg(long, conflicts_with = "no_git")]
+    git_hooks: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum BuildSystem {
+    Cmake,
+    Meson,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Language {
+    /// C++ only (LANGUAGES CXX)
+    Cxx,
+    /// C++ and Objective-C++ `.mm` sources (LANGUAGES CXX OBJCXX)
+    Objcxx,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum CmakeStyle {
+    /// Project-wide CMAKE_CXX_STANDARD, sources listed in add_executable/add_library
+    Classic,
+    /// Target-scoped commands only: target_sources, target_compile_features, explicit policies
+    Modern,
+}
+
+#[derive(Args, Clone)]
+struct InstallArgs {
+    /// When Conan may build dependencies from source: missing, never, cascade or * (always)
+    #[arg(long, default_value = "missing", value_parser = ["missing", "never", "cascade", "*"])]
+    build_policy: String,
+    /// Extra Conan generators to run besides CMakeDeps and CMakeToolchain (comma-separated)
+    #[arg(long, value_delimiter = ',', value_name = "GENERATORS", value_parser = CONAN_GENERATORS.to_vec())]
+    generators: Vec<String>,
+    /// Read the dependencies from this file instead of packages/requirements.txt
+    #[arg(long, value_name = "PATH")]
+    requirements: Option<PathBuf>,
+    /// Write the exact resolved package versions to this file (e.g. packages/resolved.txt)
+    #[arg(long, value_name = "FILE")]
+    lock_out: Option<PathBuf>,
+    /// Conan host profile to install with, overriding [install] profile in sage.toml
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+    /// Conan build profile (for the tools that run during the build), overriding [install] build_profile
+    #[arg(long, value_name = "NAME")]
+    profile_build: Option<String>,
+    /// Install for a cross-compilation target triple (e.g. aarch64-linux-gnu) into packages/install-<triple>
+    #[arg(long, value_name = "TRIPLE", value_parser = cross::parse_triple)]
+    triple: Option<cross::Triple>,
+}
+
+/// The flags' defaults, for commands that install as one of their steps.
+impl Default for InstallArgs {
+    fn default() -> Self {
+        InstallArgs { build_policy: "missing".to_string(), generators: Vec::new(), requirements: None, lock_out: None, profile: None, profile_build: None, triple: None }
+    }
+}
+
+impl InstallArgs {
+    fn rebase_paths(&mut self, base: &Path) {
+        self.requirements.iter_mut().chain(self.lock_out.iter_mut()).for_each(|path| rebase_path(path, base));
+    }
+}
+
+/// Where `sage install` reads dependencies from by default.
+const REQUIREMENTS_FILE: &str = "packages/requirements.txt";
+
+/// The default Conan generators, which sage's CMake integration relies on.
+const DEFAULT_CONAN_GENERATORS: &[&str] = &["CMakeDeps", "CMakeToolchain"];
+
+/// Conan 2 generators accepted by `install --generators`.
+const CONAN_GENERATORS: &[&str] = &[
+    "CMakeDeps",
+    "CMakeToolchain",
+    "PkgConfigDeps",
+    "VirtualBuildEnv",
+    "VirtualRunEnv",
+    "MesonToolchain",
+    "AutotoolsDeps",
+    "AutotoolsToolchain",
+    "MSBuildDeps",
+    "MSBuildToolchain",
+    "XcodeDeps",
+    "XcodeToolchain",
+    "BazelDeps",
+    "BazelToolchain",
+    "MakeDeps",
+    "NMakeDeps",
+    "NMakeToolchain",
+    "PremakeDeps",
+    "QbsDeps",
+    "SConsDeps",
+];
+
+#[derive(Args, Clone, Default)]
+struct CompileArgs {
+    /// Enable the given CMake option() features (comma-separated)
+    #[arg(long, value_delimiter = ',', value_name = "FEATURES")]
+    features: Vec<String>,
+    /// Turn off every option() declared by the project, except --features
+    #[arg(long)]
+    no_default_features: bool,
+    /// Print the SHA-256 of each built executable
+    #[arg(long)]
+    print_hash: bool,
+    /// Fail the build if any compiler warning matches this regex
+    #[arg(long, value_name = "PATTERN", value_parser = regex::Regex::new)]
+    fail_fast_on_warning: Option<regex::Regex>,
+    /// Run the CMake configure step with --trace-expand, writing the trace to a file
+    #[arg(long)]
+    trace: bool,
+    /// Where --trace writes the CMake trace
+    #[arg(long, value_name = "FILE", default_value = "build/cmake-trace.log", requires = "trace")]
+    trace_file: PathBuf,
+    /// Also print the CMake trace to the terminal
+    #[arg(long, requires = "trace")]
+    trace_echo: bool,
+    /// Keep the compiler's colored diagnostics even though sage captures its output
+    #[arg(long)]
+    color_diagnostics: bool,
+    /// Run at most N link steps in parallel (Ninja only), to keep large links from exhausting RAM
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    link_jobs: Option<u32>,
+    /// Print the compiler and version CMake selected before building
+    #[arg(long)]
+    print_compiler_version: bool,
+    /// Output format for build messages; json emits line-delimited events on stdout
+    #[arg(long, value_enum, value_name = "FMT", default_value_t = events::MessageFormat::Human)]
+    message_format: events::MessageFormat,
+    /// Hide CMake/Ninja output unless the step fails
+    #[arg(long)]
+    summary_only: bool,
+    /// Treat warnings as errors only for sources under this directory (repeatable)
+    #[arg(long, value_name = "DIR")]
+    werror_scope: Vec<PathBuf>,
+    /// Stop compiling after N errors (unlimited by default)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    max_errors: Option<u32>,
+    /// Build with PGO instrumentation, writing profile data to build/pgo-data when the program runs
+    #[arg(long, conflicts_with = "pgo_use")]
+    pgo_generate: bool,
+    /// Rebuild optimized with the profile data collected by a --pgo-generate build
+    #[arg(long)]
+    pgo_use: bool,
+    /// Record per-file compile time traces with Clang's -ftime-trace into build/traces
+    #[arg(long, alias = "timing-trace")]
+    time_trace: bool,
+    /// Build with a sanitizer into build/asan (ubsan, tsan, lsan); run sets its runtime options
+    #[arg(long, value_enum, value_name = "SANITIZER")]
+    sanitize: Option<sanitize::Sanitizer>,
+    /// Write the environment CMake/Conan run with to FILE, to compare setups
+    #[arg(long, value_name = "FILE")]
+    export_build_env: Option<PathBuf>,
+    /// With --export-build-env, keep the values of secret-looking variables
+    #[arg(long, requires = "export_build_env")]
+    no_redact: bool,
+    /// Number of parallel build jobs; 0 or unset uses all logical CPUs
+    #[arg(short = 'j', long, value_name = "N")]
+    jobs: Option<u32>,
+    /// Optimized build (CMAKE_BUILD_TYPE=Release) in its own build/release directory
+    #[arg(long, conflicts_with = "debug")]
+    release: bool,
+    /// Unoptimized build with debug info (CMAKE_BUILD_TYPE=Debug)
+    #[arg(long)]
+    debug: bool,
+    /// The CMake generator to use, remembered in sage.toml for later builds (default: Ninja)
+    #[arg(long, value_name = "NAME", value_parser = manifest::GENERATORS.to_vec())]
+    generator: Option<String>,
+    /// Report TODO/FIXME/XXX markers in the sources before building
+    #[arg(long)]
+    check_todos: bool,
+    /// Like --check-todos, but fail the build when any marker is found
+    #[arg(long)]
+    fail_on_todo: bool,
+    /// Configure and build through the debug (or, with --release, release) preset of CMakePresets.json
+    #[arg(long, conflicts_with = "generator")]
+    preset: bool,
+    /// Cross-compile for a target triple (e.g. aarch64-linux-gnu) into build/<triple>, after 'sage install --triple'
+    #[arg(long, value_name = "TRIPLE", value_parser = cross::parse_triple, conflicts_with = "preset")]
+    triple: Option<cross::Triple>,
+    /// Run the CMake configure step even when the cache is up to date
+    #[arg(long)]
+    reconfigure: bool,
+    /// After building, check that editing a header makes its dependents rebuild
+    #[arg(long)]
+    depfile_check: bool,
+    /// The markers --check-todos/--fail-on-todo look for (comma-separated)
+    #[arg(long, value_delimiter = ',', value_name = "MARKERS", default_value = "TODO,FIXME,XXX", value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    todo_markers: Vec<String>,
+    /// Offer to install missing required tools (asking first) before building
+    #[arg(long)]
+    install_missing_tools: bool,
+}
+
+impl CompileArgs {
+    /// `--trace-file` is left alone: its default lives in the build dir.
+    fn rebase_paths(&mut self, base: &Path) {
+        self.werror_scope.iter_mut().chain(self.export_build_env.iter_mut()).for_each(|path| rebase_path(path, base));
+    }
+
+    /// Release builds get a directory of their own, so switching between
+    /// release and debug doesn't reconfigure and rebuild everything; so do
+    /// cross builds, beside the native ones, and sanitized builds.
+    fn build_dir(&self) -> String {
+        let mut dir = match &self.triple {
+            Some(triple) => format!("build/{}", triple.triple),
+            None => "build".to_string(),
+        };
+        if let Some(sanitizer) = self.sanitize {
+            dir = format!("{}/{}", dir, sanitizer.dir_name());
+        }
+        if self.release {
+            dir.push_str("/release");
+        }
+        dir
+    }
+
+    /// The number of parallel build jobs, with `0` meaning all logical CPUs.
+    fn jobs(&self) -> u32 {
+        match self.jobs {
+            Some(jobs) if jobs > 0 => jobs,
+            _ => std::thread::available_parallelism().map_or(1, |n| n.get() as u32),
+        }
+    }
+
+    /// The requested `CMAKE_BUILD_TYPE`, or `None` to keep the configured one.
+    fn build_type(&self) -> Option<&'static str> {
+        if self.release {
+            Some("Release")
+        } else if self.debug {
+            Some("Debug")
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Args)]
+struct CleanArgs {
+    /// Also remove packages/install and a conanfile.txt left behind by an interrupted install
+    #[arg(long)]
+    all: bool,
+    /// Like --all, and also remove this project's dependencies from the Conan cache
+    #[arg(long)]
+    deep: bool,
+    /// Don't ask before removing packages from the shared Conan cache
+    #[arg(long, requires = "deep")]
+    yes: bool,
+}
+
+#[derive(Args, Clone)]
+struct TestArgs {
+    /// Number of tests to run concurrently (defaults to the number of CPUs)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    parallel: Option<u32>,
+    /// Run tests one at a time, for suites that can't run concurrently
+    #[arg(long, conflicts_with = "parallel")]
+    serial: bool,
+    /// Only run tests whose name matches this regex (ctest -R)
+    #[arg(long, value_name = "REGEX")]
+    filter: Option<String>,
+    #[command(flatten)]
+    compile: CompileArgs,
+}
+
+#[derive(Args, Clone)]
+struct RunArgs {
+    /// The executable target to run when the project defines several
+    #[arg(long)]
+    target: Option<String>,
+    /// Feed the contents of this file to the program's standard input
+    #[arg(long, value_name = "PATH")]
+    stdin_file: Option<PathBuf>,
+    /// Set the KEY=VALUE variables of this dotenv file in the program's environment
+    #[arg(long, value_name = "PATH")]
+    env_file: Option<PathBuf>,
+    /// Run the built program N times and summarize the results
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    repeat: u32,
+    /// With --repeat, stop after the first failing run
+    #[arg(long, requires = "repeat")]
+    stop_on_failure: bool,
+    /// Launch the program through a wrapper command, e.g. "valgrind --leak-check=full"
+    #[arg(long, value_name = "COMMAND")]
+    under: Option<String>,
+    /// Also write the program's output to this file (stderr too, unless --tee-err is given)
+    #[arg(long, value_name = "FILE", conflicts_with = "repeat")]
+    tee: Option<PathBuf>,
+    /// Also write the program's stderr to this file
+    #[arg(long, value_name = "FILE", conflicts_with = "repeat")]
+    tee_err: Option<PathBuf>,
+    /// Start the program in the background and return immediately (stop it with `sage stop`)
+    #[arg(long, conflicts_with_all = ["repeat", "tee", "tee_err"])]
+    detach: bool,
+    /// Enable core dumps for the program and report the core file if it crashes (Unix)
+    #[arg(long, conflicts_with_all = ["repeat", "detach"])]
+    capture_core: bool,
+    /// With --capture-core, open the core file in gdb (or lldb) after a crash
+    #[arg(long, requires = "capture_core")]
+    debug_core: bool,
+    /// Arguments for the program, after `--`
+    #[arg(last = true, value_name = "ARGS")]
+    program_args: Vec<String>,
+    #[command(flatten)]
+    compile: CompileArgs,
+}
+
+impl RunArgs {
+    fn rebase_paths(&mut self, base: &Path) {
+        [&mut self.stdin_file, &mut self.env_file, &mut self.tee, &mut self.tee_err].into_iter().flatten().for_each(|path| rebase_path(path, base));
+        self.compile.rebase_paths(base);
+    }
+}
+
+#[derive(Args, Clone)]
+struct DebugArgs {
+    /// The executable target to debug when the project defines several
+    #[arg(long)]
+    target: Option<String>,
+    /// The debugger to use instead of the platform's default
+    #[arg(long, value_parser = debugger::DEBUGGERS.to_vec())]
+    debugger: Option<String>,
+    /// Arguments for the program, after `--`
+    #[arg(last = true, value_name = "ARGS")]
+    program_args: Vec<String>,
+    #[command(flatten)]
+    compile: CompileArgs,
+}
+
+/// Errors are printed once here and make sage exit with status 1, so
+/// scripts, CI jobs and git hooks can tell a failed command apart.
+fn main() -> ExitCode {
+    terminal::init();
+    let result = expand_alias(env::args_os().collect()).and_then(|args| run_command(&Cli::parse_from(args)));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{} {}", "Error:".red(), e);
+            ExitCode::FAILURE
+        }
+    }
 }
 
-fn main() {
-    let cli = Cli::parse();
+fn run_command(cli: &Cli) -> Result<(), std::io::Error> {
+    if cli.no_color {
+        colored::control::set_override(false);
+    }
+    if cli.quiet {
+        events::set_verbosity(events::Verbosity::Quiet);
+    } else if cli.verbose {
+        events::set_verbosity(events::Verbosity::Verbose);
+    }
 
     match &cli.command {
-        Commands::New { name } => {
-            println!("{} {} '{}'", "Creating new project:".green(), "sage".bold(), name.bold());
-            if let Err(e) = create_project(name) {
-                eprintln!("{} {}", "Error:".red(), e);
-            } else {
-                println!("{} Project '{}' created successfully!", "Success:".green(), name);
+        Commands::New(args) => {
+            let name = &args.name;
+            events::status(format!("{} {} '{}'", "Creating new project:".green(), "sage".bold(), name.bold()));
+            create_project(args)?;
+            let root = PathBuf::from(cmake_identifier(name));
+            if !args.no_git {
+                init_git_repository(&root);
+            }
+            if args.git_hooks {
+                if root.join(".git").exists() {
+                    if let Err(e) = hooks::install(&root) {
+                        events::status(format!("{} Could not install the git hooks: {}", "Warning:".yellow(), e));
+                    }
+                } else {
+                    events::status(format!("{} No git repository was created, skipping --git-hooks", "Warning:".yellow()));
+                }
+            }
+            events::status(format!("{} Project '{}' created successfully!", "Success:".green(), name));
+            Ok(())
+        }
+        Commands::Init { force } => init_project(*force),
+        Commands::Install(args) => {
+            let mut args = args.clone();
+            args.rebase_paths(&enter_project_root()?);
+            install_dependencies(&args)
+        }
+        Commands::Compile(args) => {
+            let mut args = args.clone();
+            args.rebase_paths(&enter_project_root()?);
+            compile_project(&args)
+        }
+        Commands::Run(args) => {
+            let mut args = args.clone();
+            args.rebase_paths(&enter_project_root()?);
+            run_project(&args)
+        }
+        Commands::Stop => {
+            enter_project_root()?;
+            detach::stop()
+        }
+        Commands::Clean(args) => {
+            enter_project_root()?;
+            clean_project(args)
+        }
+        Commands::Test(args) => {
+            let mut args = args.clone();
+            args.compile.rebase_paths(&enter_project_root()?);
+            run_tests(&args)
+        }
+        Commands::Debug(args) => {
+            let mut args = args.clone();
+            args.compile.rebase_paths(&enter_project_root()?);
+            debug_project(&args)
+        }
+        Commands::Doctor { deep, fix, json } => {
+            if *json {
+                return print_tools_json();
+            }
+            if *fix {
+                bootstrap::install_missing_tools()?;
+            }
+            events::status("Checking for required tools...".green());
+            check_tools();
+            if *deep {
+                check_deep()?;
             }
+            Ok(())
+        }
+        Commands::Tidy(args) => {
+            let mut args = args.clone();
+            args.rebase_paths(&enter_project_root()?);
+            tidy::run_tidy(&args)
+        }
+        Commands::Fmt(args) => format::run_format(args),
+        Commands::VerifyReproducible(args) => {
+            let mut args = args.clone();
+            args.rebase_paths(&enter_project_root()?);
+            reproducible::verify(&args)
+        }
+        Commands::Targets => {
+            enter_project_root()?;
+            targets::print_targets()
+        }
+        Commands::CheckConfig => manifest::check_config(),
+        Commands::Dockerfile { base } => {
+            let project_name = env::current_dir()?.file_name().unwrap().to_str().unwrap().to_string();
+            docker::write_dockerfile(Path::new("."), &project_name, base)?;
+            events::status(format!("{} Dockerfile generated. Build it with: {}", "Success:".green(), "docker build -t <image> .".cyan()));
+            Ok(())
+        }
+        Commands::Add { package, install } => {
+            enter_project_root()?;
+            add_dependency(package, *install)
+        }
+        Commands::Bump { package, to } => {
+            enter_project_root()?;
+            bump_dependency(package, to)
+        }
+        Commands::Update { dry_run } => {
+            enter_project_root()?;
+            update_dependencies(*dry_run)
+        }
+        Commands::Remove { package } => {
+            enter_project_root()?;
+            remove_dependency(package)
         }
-        Commands::Install => {
-            if let Err(e) = install_dependencies() {
-                eprintln!("{} {}", "Error:".red(), e);
+        Commands::AddSource { file, target } => add_source(file, target.as_deref()),
+        Commands::Hooks { command } => match command {
+            HooksCommands::Install => hooks::install(Path::new(".")),
+        },
+        Commands::Gitignore { command } => match command {
+            GitignoreCommands::Add { patterns } => append_gitignore(Path::new(".gitignore"), patterns),
+        },
+        Commands::Deps { command } => {
+            enter_project_root()?;
+            match command {
+                DepsCommands::Why { package } => deps::why(package),
+                DepsCommands::Outdated => deps::outdated(),
             }
         }
-        Commands::Compile => {
-            if let Err(e) = compile_project() {
-                eprintln!("{} {}", "Error:".red(), e);
+    }
+}
+
+fn compile_project(args: &CompileArgs) -> Result<(), std::io::Error> {
+    compile_project_in(&args.build_dir(), args)
+}
+
+fn compile_project_in(build_dir: &str, args: &CompileArgs) -> Result<(), std::io::Error> {
+    if args.message_format == events::MessageFormat::Json {
+        events::enable_json();
+    }
+    if args.install_missing_tools {
+        bootstrap::install_missing_tools()?;
+    }
+    fs::create_dir_all(build_dir)?;
+
+    if let Some(path) = &args.export_build_env {
+        let sage_vars: &[(&str, &str)] = if args.color_diagnostics { &[("CLICOLOR_FORCE", "1")] } else { &[] };
+        buildenv::export(path, sage_vars, !args.no_redact)?;
+        events::status(format!("{} Build environment written to {}", "Note:".yellow(), path.display()));
+    }
+    if args.check_todos || args.fail_on_todo {
+        todos::check(&args.todo_markers, args.fail_on_todo)?;
+    }
+
+    let result = backend::detect(Path::new(".")).compile(build_dir, args).and_then(|build_log| {
+        events::emit_build_log(&build_log);
+        match &args.fail_fast_on_warning {
+            Some(pattern) => check_warnings(&build_log, pattern),
+            None => Ok(()),
+        }
+    });
+    if let Err(e) = &result {
+        // Failing steps carry the tool output, diagnostics included.
+        events::emit_build_log(&e.to_string());
+    }
+    events::emit("build-finished", serde_json::json!({ "success": result.is_ok() }));
+    result?;
+
+    events::status(format!("{} Project compiled successfully!", "Success:".green()));
+
+    if args.print_hash {
+        reproducible::print_hashes(Path::new(build_dir))?;
+    }
+
+    Ok(())
+}
+
+/// Fails when a compiler warning in `build_log` matches `pattern`, a lighter
+/// alternative to `-Werror` for codebases with many pre-existing warnings.
+fn check_warnings(build_log: &str, pattern: &regex::Regex) -> Result<(), std::io::Error> {
+    let matched: Vec<&str> = build_log
+        .lines()
+        .filter(|line| line.contains("warning:") || line.contains(": warning C"))
+        .filter(|line| pattern.is_match(line))
+        .collect();
+    if matched.is_empty() {
+        return Ok(());
+    }
+
+    events::status(format!("{} warning(s) matching '{}':", matched.len(), pattern).yellow());
+    for line in &matched {
+        events::status(format!("  {}", line));
+    }
+    Err(std::io::Error::other(format!("{} warning(s) matched --fail-fast-on-warning.", matched.len())))
+}
+
+/// Configures (when needed) and builds with CMake, returning the build log.
+fn cmake_compile(build_dir: &str, args: &CompileArgs) -> Result<String, std::io::Error> {
+    if let Some(generator) = &args.generator {
+        remember_generator(generator)?;
+    }
+    let generator = cmake_generator()?;
+    let preset = args.preset.then_some(if args.release { "release" } else { "debug" });
+    match preset {
+        Some(preset) => check_preset(build_dir, preset)?,
+        None => reset_cache_for_generator(build_dir, &generator)?,
+    }
+    if args.link_jobs.is_some() && !generator.starts_with("Ninja") {
+        events::status(format!("{} --link-jobs only applies to Ninja generators, ignoring it for '{}'", "Warning:".yellow(), generator));
+    }
+
+    let mut configure_args = feature_defines(args)?;
+    if args.trace {
+        if let Some(parent) = args.trace_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        configure_args.push("--trace-expand".to_string());
+        configure_args.push(format!("--trace-redirect={}", args.trace_file.display()));
+    }
+    if !cmake::is_multi_config(&generator) {
+        configure_args.extend(build_type_defines(build_dir, args));
+    }
+    configure_args.extend(link_pool_defines(build_dir, args));
+    configure_args.extend(werror_scope_defines(build_dir, args)?);
+
+    let cxx_flags = injected_cxx_flags(build_dir, args)?;
+    let flags_stamp = Path::new(build_dir).join(".sage-cxx-flags");
+    let flags_changed = fs::read_to_string(&flags_stamp).unwrap_or_default() != cxx_flags.join(" ");
+    if flags_changed {
+        let user_flags = env::var("CXXFLAGS").unwrap_or_default();
+        let all_flags: Vec<&str> = user_flags.split_whitespace().chain(cxx_flags.iter().map(|f| f.as_str())).collect();
+        configure_args.push(format!("-DCMAKE_CXX_FLAGS={}", all_flags.join(" ")));
+    }
+
+    if args.reconfigure || !configure_args.is_empty() || needs_reconfigure(Path::new("."), Path::new(build_dir)) {
+        events::emit("configure-started", serde_json::json!({ "build_dir": build_dir }));
+        let configured = configure_project(build_dir, &configure_args, preset, args.triple.as_ref());
+        events::emit("configure-finished", serde_json::json!({ "success": configured.is_ok() }));
+        // The trace matters most when configuring fails, so report it either way.
+        if args.trace {
+            if args.trace_echo {
+                events::status(fs::read_to_string(&args.trace_file).unwrap_or_default());
             }
+            events::status(format!("{} CMake trace written to {}", "Note:".yellow(), args.trace_file.display()));
+        }
+        let configure_log = configured?;
+        if events::show_tool_output(args.summary_only) {
+            println!("{}", configure_log);
+        }
+        if flags_changed {
+            fs::write(&flags_stamp, cxx_flags.join(" "))?;
+        }
+    } else {
+        events::emit("configure-skipped", serde_json::json!({ "build_dir": build_dir }));
+        events::status("CMake cache is up to date, skipping configure.".dimmed());
+    }
+
+    if args.print_compiler_version {
+        match compiler::configured_description(Path::new(build_dir)) {
+            Some(description) => events::status(format!("{} {}", "Compiler:".green(), description.bold())),
+            None => events::status(format!("{} Could not determine the compiler from {}/CMakeCache.txt", "Warning:".yellow(), build_dir)),
+        }
+    }
+
+    events::emit("build-started", serde_json::json!({ "build_dir": build_dir }));
+    events::status("Compiling project with CMake...".green());
+    // Build with CMake
+    let mut build_command = buildenv::tool_command("cmake");
+    match preset {
+        Some(preset) => build_command.args(["--build", "--preset", preset]),
+        None => build_command.args(["--build", build_dir]),
+    };
+    build_command.args(["--parallel", &args.jobs().to_string()]);
+    if preset.is_none() && cmake::is_multi_config(&generator) {
+        build_command.args(["--config", args.build_type().unwrap_or("Debug")]);
+    }
+    if args.color_diagnostics {
+        // Ninja strips colors from captured output unless forced.
+        build_command.env("CLICOLOR_FORCE", "1");
+    }
+    let pgo_rebuild = args.pgo_use && pgo::profile_changed(build_dir);
+    if pgo_rebuild {
+        events::status("Profile data changed, rebuilding everything with it...".dimmed());
+        build_command.arg("--clean-first");
+    }
+    events::command(&build_command);
+    let build_output = build_command.output()?;
+
+    let stdout = String::from_utf8_lossy(&build_output.stdout);
+    let stderr = String::from_utf8_lossy(&build_output.stderr);
+    if !build_output.status.success() {
+        // Ninja reports compiler errors on stdout, so keep both streams.
+        return Err(std::io::Error::other(format!("CMake build failed:\n{}{}", stdout, stderr)));
+    }
+    if events::show_tool_output(args.summary_only) {
+        println!("{}", stdout);
+        println!("{}", stderr);
+    }
+    if args.pgo_generate && cxx_flags.iter().any(|flag| flag.starts_with("-fprofile-generate")) {
+        pgo::print_next_step();
+    }
+    if pgo_rebuild {
+        pgo::mark_used(build_dir)?;
+    }
+    if args.time_trace && cxx_flags.iter().any(|flag| flag == "-ftime-trace") {
+        timetrace::collect(build_dir)?;
+    }
+    if args.depfile_check {
+        depfile::check(build_dir, &generator, args.build_type().unwrap_or("Debug"))?;
+    }
+
+    Ok(format!("{}{}", stdout, stderr))
+}
+
+/// Compiler flags sage adds on top of the user's `CXXFLAGS`, picked for the
+/// detected compiler. They are recorded in `build/.sage-cxx-flags` so the
+/// project is only reconfigured when the set actually changes.
+fn injected_cxx_flags(build_dir: &str, args: &CompileArgs) -> Result<Vec<String>, std::io::Error> {
+    let mut flags = Vec::new();
+    if !args.color_diagnostics && args.max_errors.is_none() && !args.pgo_generate && !args.pgo_use && !args.time_trace && args.sanitize.is_none() {
+        return Ok(flags);
+    }
+    let compiler = compiler::detect(Path::new(build_dir));
+    if args.color_diagnostics {
+        match compiler.color_diagnostics_flag() {
+            Some(flag) => flags.push(flag.to_string()),
+            None => events::status(format!("{} Colored diagnostics can't be forced for this compiler ({:?})", "Warning:".yellow(), compiler)),
         }
-        Commands::Run => {
-            if let Err(e) = run_project() {
-                eprintln!("{} {}", "Error:".red(), e);
+    }
+    if let Some(limit) = args.max_errors {
+        match compiler.max_errors_flag(limit) {
+            Some(flag) => flags.push(flag),
+            None if compiler == compiler::Compiler::Msvc => events::status(format!("{} MSVC has no error limit flag (it stops on its own after 100 errors), ignoring --max-errors", "Warning:".yellow())),
+            None => events::status(format!("{} Can't limit errors for this compiler ({:?}), ignoring --max-errors", "Warning:".yellow(), compiler)),
+        }
+    }
+    if args.pgo_generate {
+        flags.extend(pgo::flags(compiler, build_dir, pgo::Stage::Generate)?);
+    } else if args.pgo_use {
+        flags.extend(pgo::flags(compiler, build_dir, pgo::Stage::Use)?);
+    }
+    if args.time_trace {
+        flags.extend(timetrace::flags(compiler));
+    }
+    if let Some(sanitizer) = args.sanitize {
+        flags.extend(sanitize::flags(compiler, sanitizer));
+    }
+    Ok(flags)
+}
+
+/// `-DCMAKE_BUILD_TYPE` for `--release`/`--debug`, unless the cache already
+/// has that build type.
+fn build_type_defines(build_dir: &str, args: &CompileArgs) -> Vec<String> {
+    match args.build_type() {
+        Some(build_type) if compiler::cache_value(Path::new(build_dir), "CMAKE_BUILD_TYPE").as_deref() != Some(build_type) => {
+            vec![format!("-DCMAKE_BUILD_TYPE={}", build_type)]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// The Ninja job pool that `--link-jobs` assigns every link step to.
+const LINK_POOL: &str = "sage_link";
+
+/// Configure flags putting link steps in a `--link-jobs`-sized job pool, or
+/// removing the pool again once the flag is dropped. Returns nothing when the
+/// cache already matches, so an unchanged setting doesn't force a reconfigure.
+fn link_pool_defines(build_dir: &str, args: &CompileArgs) -> Vec<String> {
+    let build_dir = Path::new(build_dir);
+    let pool_configured = compiler::cache_value(build_dir, "CMAKE_JOB_POOL_LINK").as_deref() == Some(LINK_POOL);
+    match args.link_jobs {
+        Some(jobs) => {
+            let pools = format!("{}={}", LINK_POOL, jobs);
+            if pool_configured && compiler::cache_value(build_dir, "CMAKE_JOB_POOLS").as_deref() == Some(pools.as_str()) {
+                return Vec::new();
             }
+            vec![format!("-DCMAKE_JOB_POOLS={}", pools), format!("-DCMAKE_JOB_POOL_LINK={}", LINK_POOL)]
         }
-        Commands::Debug => {
-            println!("{}", "Debugging project...".green());
-            // Actual implementation will go here
+        None if pool_configured => vec!["-UCMAKE_JOB_POOLS".to_string(), "-UCMAKE_JOB_POOL_LINK".to_string()],
+        None => Vec::new(),
+    }
+}
+
+/// The script `--werror-scope` injects through `CMAKE_PROJECT_INCLUDE`. It
+/// defers until the whole source tree has been processed, then appends
+/// `-Werror` (`/WX` for MSVC) to the `COMPILE_OPTIONS` source property of
+/// every target source under one of `SAGE_WERROR_SCOPES`.
+const WERROR_SCOPE_SCRIPT: &str = r#"# Generated by cppsage for --werror-scope, do not edit.
+if(CMAKE_VERSION VERSION_LESS 3.19)
+    message(WARNING "--werror-scope needs CMake 3.19 or newer, ignoring it.")
+    return()
+endif()
+
+function(sage_collect_targets dir out)
+    get_property(targets DIRECTORY "${dir}" PROPERTY BUILDSYSTEM_TARGETS)
+    get_property(subdirs DIRECTORY "${dir}" PROPERTY SUBDIRECTORIES)
+    foreach(subdir IN LISTS subdirs)
+        sage_collect_targets("${subdir}" sub_targets)
+        list(APPEND targets ${sub_targets})
+    endforeach()
+    set(${out} ${targets} PARENT_SCOPE)
+endfunction()
+
+function(sage_apply_werror_scope)
+    sage_collect_targets("${CMAKE_SOURCE_DIR}" targets)
+    foreach(target IN LISTS targets)
+        get_target_property(type ${target} TYPE)
+        if(type STREQUAL "INTERFACE_LIBRARY" OR type STREQUAL "UTILITY")
+            continue()
+        endif()
+        get_target_property(sources ${target} SOURCES)
+        get_target_property(source_dir ${target} SOURCE_DIR)
+        foreach(source IN LISTS sources)
+            if(source MATCHES "^\$<")
+                continue()
+            endif()
+            get_filename_component(path "${source}" ABSOLUTE BASE_DIR "${source_dir}")
+            foreach(scope IN LISTS SAGE_WERROR_SCOPES)
+                string(FIND "${path}" "${scope}/" position)
+                if(position EQUAL 0)
+                    set_property(SOURCE "${path}" TARGET_DIRECTORY ${target}
+                        APPEND PROPERTY COMPILE_OPTIONS "$<IF:$<CXX_COMPILER_ID:MSVC>,/WX,-Werror>")
+                    break()
+                endif()
+            endforeach()
+        endforeach()
+    endforeach()
+endfunction()
+
+# CMAKE_PROJECT_INCLUDE runs after every project() call; apply the scope once.
+get_property(sage_werror_scope_deferred GLOBAL PROPERTY SAGE_WERROR_SCOPE_DEFERRED)
+if(NOT sage_werror_scope_deferred)
+    set_property(GLOBAL PROPERTY SAGE_WERROR_SCOPE_DEFERRED TRUE)
+    cmake_language(DEFER DIRECTORY "${CMAKE_SOURCE_DIR}" CALL sage_apply_werror_scope)
+endif()
+"#;
+
+/// Configure flags injecting `WERROR_SCOPE_SCRIPT` for the `--werror-scope`
+/// directories, or removing it once the flag is dropped. Like the link pool,
+/// nothing is returned while the cache already matches.
+fn werror_scope_defines(build_dir: &str, args: &CompileArgs) -> Result<Vec<String>, std::io::Error> {
+    let cache_dir = Path::new(build_dir);
+    let script_path = cache_dir.join("sage-werror-scope.cmake");
+    let script = cmake_path(&fs::canonicalize(cache_dir)?.join("sage-werror-scope.cmake"));
+    let injected = compiler::cache_value(cache_dir, "CMAKE_PROJECT_INCLUDE").as_deref() == Some(script.as_str());
+
+    if args.werror_scope.is_empty() {
+        return Ok(if injected { vec!["-UCMAKE_PROJECT_INCLUDE".to_string(), "-USAGE_WERROR_SCOPES".to_string()] } else { Vec::new() });
+    }
+
+    let mut scopes = Vec::new();
+    for dir in &args.werror_scope {
+        if !dir.is_dir() {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("--werror-scope directory not found: {}", dir.display())));
         }
-        Commands::Doctor => {
-            println!("{}", "Checking for required tools...".green());
-            check_tools();
+        scopes.push(cmake_path(&fs::canonicalize(dir)?).trim_end_matches('/').to_string());
+    }
+    let scopes = scopes.join(";");
+
+    // Rewriting an unchanged script would make CMake regenerate on every build.
+    if fs::read_to_string(&script_path).ok().as_deref() != Some(WERROR_SCOPE_SCRIPT) {
+        fs::write(&script_path, WERROR_SCOPE_SCRIPT)?;
+    }
+    if injected && compiler::cache_value(cache_dir, "SAGE_WERROR_SCOPES").as_deref() == Some(scopes.as_str()) {
+        return Ok(Vec::new());
+    }
+    Ok(vec![format!("-DCMAKE_PROJECT_INCLUDE={}", script), format!("-DSAGE_WERROR_SCOPES={}", scopes)])
+}
+
+/// Spells an absolute path the way CMake expects it: forward slashes and no
+/// `\\?\` verbatim prefix from `fs::canonicalize` on Windows.
+fn cmake_path(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    path.strip_prefix("//?/").map(str::to_string).unwrap_or(path)
+}
+
+/// Translates `--features`/`--no-default-features` into `-D<OPTION>=ON/OFF`
+/// flags for the `option()`s the project declares in its CMakeLists files.
+/// A feature matches an option case-insensitively, either by its full name or
+/// by a `_`-separated suffix: `tests` and `build-tests` both select
+/// `MYAPP_BUILD_TESTS`, as long as the match is unambiguous.
+fn feature_defines(args: &CompileArgs) -> Result<Vec<String>, std::io::Error> {
+    if args.features.is_empty() && !args.no_default_features {
+        return Ok(Vec::new());
+    }
+
+    let options = cmake::declared_options(Path::new("."));
+    let mut enabled = Vec::new();
+    for feature in args.features.iter().map(|f| f.trim()).filter(|f| !f.is_empty()) {
+        let wanted = feature.to_uppercase().replace('-', "_");
+        let matches: Vec<&String> = options
+            .iter()
+            .filter(|option| {
+                let option = option.to_uppercase();
+                option == wanted || option.ends_with(&format!("_{}", wanted))
+            })
+            .collect();
+        match matches.as_slice() {
+            [option] => enabled.push((*option).clone()),
+            [] => return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Unknown feature '{}'. Declared options: {}", feature, options.join(", ")))),
+            _ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Feature '{}' is ambiguous, it matches: {}", feature, matches.iter().map(|o| o.as_str()).collect::<Vec<_>>().join(", ")))),
         }
     }
+
+    let mut defines: Vec<String> = enabled.iter().map(|option| format!("-D{}=ON", option)).collect();
+    if args.no_default_features {
+        defines.extend(options.iter().filter(|o| !enabled.contains(o)).map(|option| format!("-D{}=OFF", option)));
+    }
+    Ok(defines)
 }
 
-fn compile_project() -> Result<(), std::io::Error> {
-    println!("{}", "Configuring project with CMake...".green());
+/// The CMake generator to configure with: `[build] generator` from
+/// `sage.toml`, or Ninja.
+fn cmake_generator() -> Result<String, std::io::Error> {
+    Ok(manifest::load(Path::new("."))?
+        .and_then(|m| m.build.generator)
+        .unwrap_or_else(|| default_generator().to_string()))
+}
 
-    let build_dir = "build";
-    fs::create_dir_all(build_dir)?;
-    
-    let toolchain_path = "packages/install/conan_toolchain.cmake";
+/// Ninja, or the platform's Makefiles generator when Ninja isn't installed,
+/// so the default build still works without it. The fallback is announced
+/// once; a generator chosen with `--generator` is never replaced.
+fn default_generator() -> &'static str {
+    static GENERATOR: OnceLock<&'static str> = OnceLock::new();
+    GENERATOR.get_or_init(|| {
+        let ninja = REQUIRED_TOOLS.iter().find(|tool| tool.name == "ninja").expect("ninja is a required tool");
+        if tool_status(ninja).found {
+            return cmake::DEFAULT_GENERATOR;
+        }
+        let fallback = if cfg!(target_os = "windows") { "NMake Makefiles" } else { "Unix Makefiles" };
+        events::status(format!("{} Ninja not found, using the '{}' generator", "Note:".yellow(), fallback));
+        events::status(format!("  {}", "Install Ninja for faster builds, 'sage doctor --fix' can do it.".cyan()));
+        fallback
+    })
+}
+
+/// Saves a `--generator` choice to `sage.toml`, so later `compile`/`run`
+/// calls keep using it without repeating the flag.
+fn remember_generator(generator: &str) -> Result<(), std::io::Error> {
+    if cmake_generator()? == generator {
+        return Ok(());
+    }
+    manifest::set_value(Path::new("."), "build", "generator", generator)?;
+    events::status(format!("{} Saved generator '{}' to {}, later builds use it too", "Note:".yellow(), generator, manifest::MANIFEST_FILE));
+    Ok(())
+}
+
+/// CMake refuses to switch generators in an existing build tree, so the
+/// cache (and the flags recorded alongside it) are dropped when the
+/// generator changed; the next configure then starts fresh.
+fn reset_cache_for_generator(build_dir: &str, generator: &str) -> Result<(), std::io::Error> {
+    let build_dir = Path::new(build_dir);
+    let Some(configured) = compiler::cache_value(build_dir, "CMAKE_GENERATOR") else {
+        return Ok(());
+    };
+    if configured == generator {
+        return Ok(());
+    }
+    events::status(format!("{} {} was configured for '{}', reconfiguring it for '{}'", "Note:".yellow(), build_dir.display(), configured, generator));
+    fs::remove_file(build_dir.join("CMakeCache.txt"))?;
+    if build_dir.join("CMakeFiles").is_dir() {
+        fs::remove_dir_all(build_dir.join("CMakeFiles"))?;
+    }
+    let flags_stamp = build_dir.join(".sage-cxx-flags");
+    if flags_stamp.exists() {
+        fs::remove_file(flags_stamp)?;
+    }
+    Ok(())
+}
+
+/// sage keeps its own build directories with `--preset` (caches, `run` and
+/// the flag stamps all live there), so the preset has to build into the one
+/// sage expects.
+fn check_preset(build_dir: &str, preset: &str) -> Result<(), std::io::Error> {
+    let binary_dir = cmake::configure_preset_binary_dir(Path::new("."), preset)?;
+    if !binary_dir.is_empty() && binary_dir.trim_end_matches('/') != build_dir {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!(
+            "The '{}' preset builds into '{}', but sage expects '{}'. Set its binaryDir to \"${{sourceDir}}/{}\".",
+            preset, binary_dir, build_dir, build_dir
+        )));
+    }
+    Ok(())
+}
+
+/// Runs the CMake configure step, returning its output. With a `preset`, the
+/// generator and toolchain come from `CMakePresets.json`; with a `triple`,
+/// the toolchain is the one `sage install --triple` generated for it.
+fn configure_project(build_dir: &str, extra_args: &[String], preset: Option<&str>, triple: Option<&cross::Triple>) -> Result<String, std::io::Error> {
+    events::status("Configuring project with CMake...".green());
+    let toolchain_path = conan_toolchain_file(triple)?;
+    if let Err(problem) = validate_toolchain_file(&toolchain_path) {
+        events::status(format!("{} {} {}", "Warning:".yellow(), toolchain_path.display(), problem));
+        events::status(format!("  {}", "Re-run 'sage install' to regenerate it.".cyan()));
+    }
+    if triple.is_some() {
+        refresh_config_cmake()?;
+    }
+
+    let mut configure_command = buildenv::tool_command("cmake");
+    if let Some(preset) = preset {
+        configure_command.args(["--preset", preset]).args(extra_args);
+        events::command(&configure_command);
+        let configure_output = configure_command.output()?;
+        let stdout = String::from_utf8_lossy(&configure_output.stdout);
+        let stderr = String::from_utf8_lossy(&configure_output.stderr);
+        if !configure_output.status.success() {
+            return Err(std::io::Error::other(format!("CMake configuration failed:\n{}{}", stdout, stderr)));
+        }
+        return Ok(format!("{}\n{}", stdout, stderr));
+    }
+    let generator = cmake_generator()?;
+    reset_cache_for_generator(build_dir, &generator)?;
 
     // Configure with CMake
-    let configure_output = Command::new("cmake")
-        .args(&[
+    configure_command
+        .args([
             "-S", ".",
             "-B", build_dir,
-            "-G", "Ninja",
-            &format!("-DCMAKE_TOOLCHAIN_FILE={}", toolchain_path)
+            "-G", &generator,
+            &format!("-DCMAKE_TOOLCHAIN_FILE={}", cmake_path(&toolchain_path)),
+            "-DCMAKE_EXPORT_COMPILE_COMMANDS=ON",
         ])
-        .output()?;
+        .args(triple.map(cross::Triple::cmake_defines).unwrap_or_default())
+        .args(extra_args);
+    events::command(&configure_command);
+    let configure_output = configure_command.output()?;
 
+    let stdout = String::from_utf8_lossy(&configure_output.stdout);
+    let stderr = String::from_utf8_lossy(&configure_output.stderr);
     if !configure_output.status.success() {
-        let stderr = String::from_utf8_lossy(&configure_output.stderr);
-        return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("CMake configuration failed:\n{}", stderr)));
+        return Err(std::io::Error::other(format!("CMake configuration failed:\n{}{}", stdout, stderr)));
+    }
+
+    Ok(format!("{}\n{}", stdout, stderr))
+}
+
+/// The nearest enclosing directory that is a project root: one with a
+/// `sage.toml`, a `CMakeLists.txt` including `cmake/config.cmake` (sub-project
+/// CMakeLists don't), or a `meson.build` declaring the `project()`. A plain
+/// CMake project in the current directory still counts, as it always has.
+fn find_project_root() -> Result<PathBuf, std::io::Error> {
+    let cwd = env::current_dir()?;
+    let contains = |path: PathBuf, needle: &str| fs::read_to_string(path).is_ok_and(|content| content.contains(needle));
+    let root = cwd.ancestors().find(|dir| {
+        dir.join(manifest::MANIFEST_FILE).is_file()
+            || contains(dir.join("CMakeLists.txt"), "include(cmake/config.cmake)")
+            || contains(dir.join("meson.build"), "project(")
+    });
+    match root {
+        Some(root) => Ok(root.to_path_buf()),
+        None if cwd.join("CMakeLists.txt").is_file() => Ok(cwd),
+        None => Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!(
+            "No sage project found in {} or any parent directory (looked for {}, or a CMakeLists.txt including cmake/config.cmake). Run 'sage new' or 'sage init' first.",
+            cwd.display(), manifest::MANIFEST_FILE
+        ))),
+    }
+}
+
+/// Moves into the project root so the commands' relative paths (`build/`,
+/// `packages/`) resolve from any subdirectory. Returns the directory sage was
+/// started in, for rebasing the paths given on the command line.
+fn enter_project_root() -> Result<PathBuf, std::io::Error> {
+    let cwd = env::current_dir()?;
+    let root = find_project_root()?;
+    if root != cwd {
+        env::set_current_dir(&root)?;
+        events::status(format!("Using the project in {}", root.display()).dimmed());
+    }
+    Ok(cwd)
+}
+
+/// Makes a relative command-line path absolute against `base`, the
+/// directory sage was started in.
+fn rebase_path(path: &mut PathBuf, base: &Path) {
+    if path.is_relative() {
+        *path = base.join(&*path);
+    }
+}
+
+/// The absolute path of Conan's toolchain file in the project root, so
+/// CMake finds it whatever directory sage runs from. Fails when `sage install`
+/// hasn't generated it yet, which CMake would only report as an unreadable
+/// toolchain. Cross builds use the toolchain installed for their triple.
+fn conan_toolchain_file(triple: Option<&cross::Triple>) -> Result<PathBuf, std::io::Error> {
+    let install_dir = triple.map_or_else(|| "packages/install".to_string(), cross::Triple::install_dir);
+    let path = find_project_root()?.join(install_dir).join("conan_toolchain.cmake");
+    if !path.is_file() {
+        let install = triple.map_or_else(|| "sage install".to_string(), |triple| format!("sage install --triple {}", triple.triple));
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!(
+            "Conan toolchain not found at {}. Run '{}' first to generate it.", path.display(), install
+        )));
+    }
+    Ok(path)
+}
+
+/// Projects created before cross builds include the native Conan toolchain
+/// from `cmake/config.cmake` unconditionally, which would override the
+/// cross one; the managed file is brought up to date before a cross build.
+fn refresh_config_cmake() -> Result<(), std::io::Error> {
+    let path = find_project_root()?.join("cmake/config.cmake");
+    let Ok(content) = fs::read_to_string(&path) else {
+        return Ok(());
+    };
+    if content.contains("# This file is managed by cppsage.") && !content.contains("if(CMAKE_TOOLCHAIN_FILE)") {
+        fs::write(&path, CONFIG_CMAKE_CONTENT)?;
+        events::status(format!("{} Updated {} so the native Conan toolchain doesn't override the cross one", "Note:".yellow(), path.display()));
+    }
+    Ok(())
+}
+
+/// A cheap sanity check of Conan's generated toolchain file, catching an
+/// interrupted install before it turns into a confusing configure failure.
+fn validate_toolchain_file(path: &Path) -> Result<(), String> {
+    let content = fs::read_to_string(path).map_err(|_| "was not found".to_string())?;
+    if content.trim().is_empty() {
+        return Err("is empty".to_string());
+    }
+    if !content.contains("set(CMAKE_") {
+        return Err("defines no CMAKE_ variables".to_string());
+    }
+    // A truncated write almost always leaves a command call unterminated.
+    let code: String = content.lines().map(|line| line.split('#').next().unwrap_or("")).collect();
+    if code.matches('(').count() != code.matches(')').count() {
+        return Err("looks truncated (unbalanced parentheses)".to_string());
+    }
+    Ok(())
+}
+
+/// Returns true when the build directory has no CMake cache yet, or when any
+/// CMake input (a `CMakeLists.txt` or `*.cmake` file, or `sage.toml`) is
+/// newer than the cache.
+/// Some generators re-run CMake on their own when inputs change, others don't,
+/// so this makes the decision explicit instead of relying on the generator.
+fn needs_reconfigure(source_dir: &Path, build_dir: &Path) -> bool {
+    let cache_modified = match fs::metadata(build_dir.join("CMakeCache.txt")).and_then(|m| m.modified()) {
+        Ok(modified) => modified,
+        Err(_) => return true,
+    };
+    let manifest_modified = fs::metadata(source_dir.join(manifest::MANIFEST_FILE)).and_then(|m| m.modified()).ok();
+    match newest_cmake_input(source_dir, build_dir).max(manifest_modified) {
+        Some(newest) => newest > cache_modified,
+        None => false,
+    }
+}
+
+fn newest_cmake_input(dir: &Path, build_dir: &Path) -> Option<std::time::SystemTime> {
+    let mut newest = None;
+    let entries = fs::read_dir(dir).ok()?;
+    for entry in entries.map_while(Result::ok) {
+        let path = entry.path();
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        let modified = if path.is_dir() {
+            if file_name.starts_with('.') || file_name == "build" || same_path(&path, build_dir) {
+                continue;
+            }
+            newest_cmake_input(&path, build_dir)
+        } else if file_name == "CMakeLists.txt" || file_name.ends_with(".cmake") {
+            entry.metadata().and_then(|m| m.modified()).ok()
+        } else {
+            None
+        };
+        if modified > newest {
+            newest = modified;
+        }
+    }
+    newest
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn run_project(args: &RunArgs) -> Result<(), std::io::Error> {
+    if let Some(path) = args.stdin_file.as_ref().filter(|path| !path.is_file()) {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("stdin file not found: {}", path.display())));
+    }
+    let wrapper = match &args.under {
+        Some(under) => split_command_line(under).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Invalid --under command: {}", e)))?,
+        None => Vec::new(),
+    };
+    // The env file comes last, so it can override the sanitizer defaults.
+    let mut env_vars = args.compile.sanitize.map(sanitize::runtime_env).unwrap_or_default();
+    if let Some(path) = &args.env_file {
+        env_vars.extend(dotenv::load(path)?);
+    }
+
+    // First, compile the project
+    compile_project(&args.compile)?;
+
+    let (target, exe_path) = built_executable(&args.compile, args.target.as_deref())?;
+    events::status(format!("{} {}", "Running".green(), target.bold()));
+
+    if let Some(program) = wrapper.first() {
+        events::status(format!("{} {}", "Under:".dimmed(), wrapper.join(" ").dimmed()));
+        if find_program(program).is_none() && !Path::new(program).is_file() {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("Wrapper '{}' not found in PATH.", program)));
+        }
+    }
+
+    if args.detach {
+        return detach::spawn_detached(program_command(&exe_path, &wrapper).args(&args.program_args).envs(env_vars).stdin(program_stdin(args, false)?));
+    }
+    if args.repeat > 1 {
+        return repeat_runs(&exe_path, &wrapper, &env_vars, args);
+    }
+
+    let mut command = if args.capture_core {
+        coredump::command(&exe_path, &wrapper)
+    } else {
+        program_command(&exe_path, &wrapper)
+    };
+    command.args(&args.program_args).envs(env_vars).stdin(program_stdin(args, true)?);
+    events::command(&command);
+    let started = std::time::SystemTime::now();
+    let status = if args.tee.is_some() || args.tee_err.is_some() {
+        tee_run(&mut command, args)?
+    } else {
+        // Inherited stdio: output shows up as it is written and prompts can
+        // read from the terminal.
+        events::status("--- Program Output ---");
+        let status = command.status()?;
+        events::status("--- End Program Output ---");
+        status
+    };
+    if args.capture_core {
+        coredump::report(&status, &exe_path, started, args.debug_core)?;
+    }
+
+    if !status.success() {
+        return Err(std::io::Error::other(format!("Project execution failed: {}", describe_exit(&status))));
+    }
+
+    Ok(())
+}
+
+/// Picks the executable target to run (see `targets::select_target`) and
+/// finds its binary in the build directory `compile` built into.
+fn built_executable(compile: &CompileArgs, requested: Option<&str>) -> Result<(String, PathBuf), std::io::Error> {
+    let project_name = env::current_dir()?.file_name().unwrap().to_str().unwrap().to_string();
+    let targets = backend::detect(Path::new(".")).executable_targets(Path::new("."), &project_name);
+    let target = if targets.is_empty() && requested.is_none() {
+        project_name
+    } else {
+        targets::select_target(&targets, requested)?
+    };
+
+    let build_dir = compile.build_dir();
+    let config = match backend::is_meson_project(Path::new(".")) {
+        false if cmake::is_multi_config(&cmake_generator()?) => Some(compile.build_type().unwrap_or("Debug")),
+        _ => None,
+    };
+    match targets::find_executable(Path::new(&build_dir), &target, config) {
+        Some(path) => Ok((target, path)),
+        None => Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("Executable for target '{}' not found under {}/", target, build_dir))),
+    }
+}
+
+/// Compiles a debug build (unless `--release` asks otherwise) and launches
+/// the executable under a debugger, with the terminal handed over to it.
+fn debug_project(args: &DebugArgs) -> Result<(), std::io::Error> {
+    let mut compile = args.compile.clone();
+    if !compile.release {
+        compile.debug = true;
+    } else {
+        events::status(format!("{} Debugging an optimized build, variables and stepping may be unreliable", "Warning:".yellow()));
+    }
+    compile_project(&compile)?;
+
+    let (target, exe_path) = built_executable(&compile, args.target.as_deref())?;
+    let debugger = debugger::find(args.debugger.as_deref())?;
+    events::status(format!("{} {} {} {}", "Debugging".green(), target.bold(), "with".green(), debugger));
+    debugger::command(debugger, &exe_path, &args.program_args).status()?;
+    Ok(())
+}
+
+/// The command launching the built program, prefixed with the `--under`
+/// wrapper (if any).
+fn program_command(exe_path: &Path, wrapper: &[String]) -> Command {
+    match wrapper.split_first() {
+        Some((program, wrapper_args)) => {
+            let mut command = Command::new(program);
+            command.args(wrapper_args).arg(exe_path);
+            command
+        }
+        None => Command::new(exe_path),
+    }
+}
+
+/// Expands a leading `sage.toml` `[alias]` into its command line, following
+/// aliases of aliases. Built-in commands always win over aliases. The
+/// manifest is the project root's, so aliases work from any subdirectory.
+fn expand_alias(mut args: Vec<OsString>) -> Result<Vec<OsString>, std::io::Error> {
+    let command = Cli::command();
+    let is_alias_candidate = |args: &[OsString]| {
+        args.get(1)
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| !name.starts_with('-') && command.find_subcommand(name).is_none())
+    };
+    if !is_alias_candidate(&args) {
+        return Ok(args);
+    }
+    // Outside a project there are no aliases, and clap reports the unknown command.
+    let Ok(root) = find_project_root() else {
+        return Ok(args);
+    };
+    let Some(manifest) = manifest::load(&root)? else {
+        return Ok(args);
+    };
+
+    let mut expanded: Vec<String> = Vec::new();
+    while is_alias_candidate(&args) {
+        let name = args[1].to_string_lossy().into_owned();
+        let Some(expansion) = manifest.alias.get(&name) else {
+            break;
+        };
+        if expanded.contains(&name) {
+            expanded.push(name);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Alias loop detected: {}", expanded.join(" -> "))));
+        }
+        let words = split_command_line(expansion)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Invalid alias '{}': {}", name, e)))?;
+        args.splice(1..2, words.into_iter().map(OsString::from));
+        expanded.push(name);
+    }
+    Ok(args)
+}
+
+/// Splits a command line into words like a POSIX shell would, honouring
+/// single quotes, double quotes and backslash escapes.
+fn split_command_line(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err("unterminated double quote".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated double quote".to_string()),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                match chars.next() {
+                    Some(c) => word.push(c),
+                    None => return Err("trailing backslash".to_string()),
+                }
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    if words.is_empty() {
+        return Err("empty command".to_string());
+    }
+    Ok(words)
+}
+
+/// The program's stdin: the `--stdin-file`, else the terminal when
+/// `interactive` (a plain run), else nothing, so repeated and detached runs
+/// never block on input.
+fn program_stdin(args: &RunArgs, interactive: bool) -> Result<Stdio, std::io::Error> {
+    match &args.stdin_file {
+        Some(path) => Ok(Stdio::from(fs::File::open(path)?)),
+        None if interactive => Ok(Stdio::inherit()),
+        None => Ok(Stdio::null()),
+    }
+}
+
+fn print_program_output(output: &std::process::Output) {
+    events::status("--- Program Output ---");
+    println!("{}", String::from_utf8_lossy(&output.stdout));
+    eprintln!("{}", String::from_utf8_lossy(&output.stderr));
+    events::status("--- End Program Output ---");
+}
+
+/// Runs the program, streaming its output to the terminal while copying it
+/// into the `--tee`/`--tee-err` files.
+fn tee_run(command: &mut Command, args: &RunArgs) -> Result<std::process::ExitStatus, std::io::Error> {
+    let out_file = args.tee.as_ref().map(fs::File::create).transpose()?;
+    let err_file = match (&args.tee_err, &out_file) {
+        (Some(path), _) => Some(fs::File::create(path)?),
+        (None, Some(file)) => Some(file.try_clone()?),
+        (None, None) => None,
+    };
+
+    let mut child = command.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let child_stdout = child.stdout.take().expect("stdout is piped");
+    let child_stderr = child.stderr.take().expect("stderr is piped");
+
+    events::status("--- Program Output ---");
+    let stderr_copy = std::thread::spawn(move || tee_stream(child_stderr, std::io::stderr(), err_file));
+    tee_stream(child_stdout, std::io::stdout(), out_file)?;
+    stderr_copy.join().map_err(|_| std::io::Error::other("Copying the program's stderr failed."))??;
+    let status = child.wait()?;
+    events::status("--- End Program Output ---");
+
+    for path in [&args.tee, &args.tee_err].into_iter().flatten() {
+        events::status(format!("{} Output saved to {}", "Note:".yellow(), path.display()));
+    }
+    Ok(status)
+}
+
+/// Describes how a program ended: its exit code, or on Unix the signal that
+/// killed it, e.g. `exit code 3` or `killed by signal 11 (SIGSEGV)`.
+fn describe_exit(status: &std::process::ExitStatus) -> String {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return match signal_name(signal) {
+                Some(name) => format!("killed by signal {} ({})", signal, name),
+                None => format!("killed by signal {}", signal),
+            };
+        }
+    }
+    match status.code() {
+        // Windows reports crashes as NTSTATUS codes, which read best in hex.
+        Some(code) if cfg!(windows) && code < 0 => match ntstatus_name(code as u32) {
+            Some(name) => format!("exit code 0x{:08X} ({})", code as u32, name),
+            None => format!("exit code 0x{:08X}", code as u32),
+        },
+        Some(code) => format!("exit code {}", code),
+        None => "terminated without an exit code".to_string(),
+    }
+}
+
+#[cfg(unix)]
+fn signal_name(signal: i32) -> Option<&'static str> {
+    Some(match signal {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        3 => "SIGQUIT",
+        4 => "SIGILL",
+        5 => "SIGTRAP",
+        6 => "SIGABRT",
+        7 if cfg!(target_os = "linux") => "SIGBUS",
+        8 => "SIGFPE",
+        9 => "SIGKILL",
+        10 if !cfg!(target_os = "linux") => "SIGBUS",
+        11 => "SIGSEGV",
+        13 => "SIGPIPE",
+        14 => "SIGALRM",
+        15 => "SIGTERM",
+        _ => return None,
+    })
+}
+
+fn ntstatus_name(code: u32) -> Option<&'static str> {
+    Some(match code {
+        0xC000_0005 => "access violation",
+        0xC000_001D => "illegal instruction",
+        0xC000_0094 => "integer division by zero",
+        0xC000_00FD => "stack overflow",
+        0xC000_0374 => "heap corruption",
+        0xC000_0409 => "stack buffer overrun or abort",
+        _ => return None,
+    })
+}
+
+fn tee_stream(mut reader: impl Read, mut terminal: impl Write, mut file: Option<fs::File>) -> Result<(), std::io::Error> {
+    let mut buffer = [0; 8192];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+        terminal.write_all(&buffer[..read])?;
+        terminal.flush()?;
+        if let Some(file) = &mut file {
+            file.write_all(&buffer[..read])?;
+        }
+    }
+}
+
+/// Runs the already-built program `--repeat` times, reporting each run's exit
+/// code and duration, to shake out flaky crashes and races.
+fn repeat_runs(exe_path: &Path, wrapper: &[String], env_vars: &[(String, String)], args: &RunArgs) -> Result<(), std::io::Error> {
+    let mut failures = 0;
+    let mut runs = 0;
+    for run in 1..=args.repeat {
+        let started = std::time::Instant::now();
+        let output = program_command(exe_path, wrapper).args(&args.program_args).envs(env_vars.iter().cloned()).stdin(program_stdin(args, false)?).output()?;
+        let elapsed = started.elapsed();
+        runs += 1;
+
+        let status = describe_exit(&output.status);
+        if output.status.success() {
+            println!("Run {}/{}: {} {}", run, args.repeat, status.green(), format!("({:.2?})", elapsed).dimmed());
+        } else {
+            failures += 1;
+            println!("Run {}/{}: {} {}", run, args.repeat, status.red(), format!("({:.2?})", elapsed).dimmed());
+            print_program_output(&output);
+            if args.stop_on_failure {
+                break;
+            }
+        }
+    }
+
+    println!("\n{} runs, {} succeeded, {} failed", runs, (runs - failures).to_string().green(), failures.to_string().red());
+    if failures > 0 {
+        return Err(std::io::Error::other(format!("{} of {} runs failed.", failures, runs)));
+    }
+    Ok(())
+}
+
+/// Removes the build directory and, with `--deep`, everything `sage install`
+/// produced, including this project's packages in the Conan cache.
+fn clean_project(args: &CleanArgs) -> Result<(), std::io::Error> {
+    // CMakeCache.txt and CMakeFiles/ at the root are left by an accidental
+    // in-source `cmake .` and would take precedence over build/.
+    let mut paths = vec!["build", "CMakeCache.txt", "CMakeFiles"];
+    if args.all || args.deep {
+        paths.extend(["packages/install", "conanfile.txt"]);
+    }
+
+    let mut removed_any = false;
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else if path.exists() {
+            fs::remove_file(path)?;
+        } else {
+            continue;
+        }
+        removed_any = true;
+        events::status(format!("{} {}", "Removed".green(), path.display()));
+    }
+
+    if args.deep {
+        remove_cached_dependencies(args.yes)?;
+    } else if !removed_any {
+        events::status(format!("{} Nothing to clean, there are no build artifacts.", "Note:".yellow()));
+        return Ok(());
+    }
+    events::status(format!("{} Project cleaned.", "Success:".green()));
+    Ok(())
+}
+
+/// Asks "Continue? [y/N]" on the terminal. Without one, fails with
+/// `refusal` rather than guessing.
+fn confirm(refusal: &str) -> Result<bool, std::io::Error> {
+    if !std::io::stdin().is_terminal() {
+        return Err(std::io::Error::other(refusal.to_string()));
+    }
+    print!("Continue? [y/N] ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Removes the project's direct requirements from the Conan cache so the
+/// next `sage install` rebuilds them. The cache is shared between projects,
+/// so this asks first.
+fn remove_cached_dependencies(yes: bool) -> Result<(), std::io::Error> {
+    let dependencies = project_dependencies()?;
+    if dependencies.is_empty() {
+        events::status(format!("{} No dependencies to remove from the Conan cache.", "Note:".yellow()));
+        return Ok(());
+    }
+
+    // Version ranges don't name a single cache entry, so remove every version.
+    let patterns: Vec<String> = dependencies
+        .iter()
+        .map(|dep| match dep.split_once('/') {
+            Some((name, version)) if version.starts_with('[') => format!("{}/*", name),
+            _ => dep.clone(),
+        })
+        .collect();
+
+    println!("{}", "These packages will be removed from the shared Conan cache:".yellow());
+    for pattern in &patterns {
+        println!("  {}", pattern);
+    }
+    if !yes && !confirm("Refusing to modify the Conan cache without confirmation. Pass --yes to proceed.")? {
+        events::status(format!("{} Left the Conan cache untouched.", "Note:".yellow()));
+        return Ok(());
+    }
+
+    for pattern in &patterns {
+        let output = Command::new("conan").args(["remove", pattern, "-c"]).output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(std::io::Error::other(format!("conan remove {} failed:\n{}", pattern, stderr)));
+        }
+        events::status(format!("{} {} from the Conan cache", "Removed".green(), pattern));
+    }
+    Ok(())
+}
+
+/// Compiles the project and runs its tests through CTest.
+fn run_tests(args: &TestArgs) -> Result<(), std::io::Error> {
+    compile_project(&args.compile)?;
+
+    let parallelism = if args.serial {
+        1
+    } else {
+        args.parallel.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get() as u32))
+    };
+
+    events::status("Running tests with CTest...".green());
+    let mut command = buildenv::tool_command("ctest");
+    command.current_dir(args.compile.build_dir()).args(["-j", &parallelism.to_string()]);
+    if let Some(filter) = &args.filter {
+        command.args(["-R", filter]);
+    }
+    if events::verbose() {
+        command.arg("--output-on-failure");
+    }
+    events::command(&command);
+
+    let mut child = command.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let child_stdout = child.stdout.take().expect("stdout is piped");
+    let child_stderr = child.stderr.take().expect("stderr is piped");
+    let stderr_copy = std::thread::spawn(move || stream_ctest_output(child_stderr, true));
+    let no_tests_on_stdout = stream_ctest_output(child_stdout, false)?;
+    let no_tests_on_stderr = stderr_copy.join().map_err(|_| std::io::Error::other("Reading CTest's stderr failed."))??;
+    let status = child.wait()?;
+    events::status(format!("Parallelism: {}", if parallelism == 1 { "serial".to_string() } else { format!("{} jobs", parallelism) }));
+
+    if no_tests_on_stdout || no_tests_on_stderr {
+        match &args.filter {
+            Some(filter) => events::status(format!("{} No tests match the filter '{}'.", "Warning:".yellow(), filter)),
+            None => {
+                events::status(format!("{} No tests were found.", "Warning:".yellow()));
+                events::status(format!("  {}", "Register tests with enable_testing() and add_test() in CMakeLists.txt".cyan()));
+            }
+        }
+        return Ok(());
+    }
+    if !status.success() {
+        return Err(std::io::Error::other("Some tests failed."));
+    }
+    events::status(format!("{} All tests passed!", "Success:".green()));
+
+    Ok(())
+}
+
+/// Echoes CTest's output line by line as it runs, returning whether CTest
+/// reported that no tests were registered (or matched the filter).
+fn stream_ctest_output(reader: impl Read, to_stderr: bool) -> Result<bool, std::io::Error> {
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    let mut no_tests = false;
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        let text = String::from_utf8_lossy(&line);
+        no_tests |= text.contains("No tests were found");
+        if to_stderr {
+            eprint!("{}", text);
+        } else {
+            print!("{}", text);
+        }
+        line.clear();
+    }
+    Ok(no_tests)
+}
+
+/// Adds `package` to `packages/requirements.txt`, replacing an entry for the
+/// same package (e.g. another version) instead of listing it twice.
+fn add_dependency(package: &str, install: bool) -> Result<(), std::io::Error> {
+    let reference = normalize_requirement(package.trim());
+    if reference != package.trim() {
+        events::status(format!("{} '{}' is not a Conan reference, using '{}'", "Note:".yellow(), package, reference));
+    }
+    let shape = regex::Regex::new(r"^[a-z0-9_][a-z0-9_+.-]*/[^/@\s]+(@[^/@\s]+/[^/@\s]+)?$").unwrap();
+    if !shape.is_match(&reference) {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("'{}' is not a valid package reference. Use name/version, e.g. fmt/10.2.1", package)));
+    }
+    let name = reference.split('/').next().unwrap_or_default();
+
+    let requirements_path = editable_requirements()?;
+    let content = fs::read_to_string(requirements_path)?;
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let existing = lines.iter().position(|line| {
+        let line = line.trim();
+        !line.starts_with('#') && normalize_requirement(line).split('/').next() == Some(name)
+    });
+
+    match existing {
+        Some(index) if normalize_requirement(lines[index].trim()) == reference => {
+            events::status(format!("{} {} is already in {}", "Note:".yellow(), reference, REQUIREMENTS_FILE));
+            return Ok(());
+        }
+        Some(index) => {
+            events::status(format!("{} {} -> {} in {}", "Updated".green(), lines[index].trim(), reference, REQUIREMENTS_FILE));
+            lines[index] = reference;
+        }
+        None => {
+            events::status(format!("{} {} to {}", "Added".green(), reference, REQUIREMENTS_FILE));
+            lines.push(reference);
+        }
+    }
+    fs::write(requirements_path, lines.join("\n") + "\n")?;
+
+    if install {
+        install_dependencies(&InstallArgs::default())?;
+    }
+    Ok(())
+}
+
+/// Changes the version of the listed `package` to `version`, after checking
+/// with Conan that the new reference resolves, then reinstalls. The old line
+/// is restored when the install fails.
+fn bump_dependency(package: &str, version: &str) -> Result<(), std::io::Error> {
+    let name = package.trim();
+    let version = version.trim();
+    if name.contains('/') || version.is_empty() || version.contains(['/', '@']) || version.contains(char::is_whitespace) {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Pass the package name and the bare version, e.g. sage bump fmt --to 11.0.0"));
+    }
+
+    let requirements_path = editable_requirements()?;
+    let content = fs::read_to_string(requirements_path)?;
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let Some(index) = lines.iter().position(|line| {
+        let line = line.trim();
+        !line.starts_with('#') && normalize_requirement(line).split('/').next() == Some(name)
+    }) else {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("'{}' is not listed in {}. Add it with: sage add {}/{}", name, REQUIREMENTS_FILE, name, version)));
+    };
+
+    // Keep a `@user/channel` suffix as it is.
+    let old_reference = normalize_requirement(lines[index].trim());
+    let old_spec = old_reference.split_once('/').map_or("", |(_, spec)| spec);
+    let (old_version, channel) = match old_spec.split_once('@') {
+        Some((old_version, channel)) => (old_version, format!("@{}", channel)),
+        None => (old_spec, String::new()),
+    };
+    if old_version == version {
+        events::status(format!("{} {} is already at {}", "Note:".yellow(), name, version));
+        return Ok(());
+    }
+    let reference = format!("{}/{}{}", name, version, channel);
+
+    events::status(format!("{} {} with Conan...", "Resolving".green(), reference));
+    let profile = manifest::load(Path::new("."))?.and_then(|m| m.install.profile);
+    let output = Command::new("conan")
+        .args(["graph", "info", &format!("--requires={}", reference)])
+        .args(profile.map(|profile| format!("--profile={}", profile)))
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(std::io::Error::other(format!("Conan could not resolve {}, {} was left unchanged:\n{}", reference, REQUIREMENTS_FILE, stderr)));
+    }
+
+    lines[index] = reference;
+    fs::write(requirements_path, lines.join("\n") + "\n")?;
+    events::status(format!("{} {} {} -> {}", "Bumped".green(), name, old_version, version));
+
+    if let Err(e) = install_dependencies(&InstallArgs::default()) {
+        fs::write(requirements_path, content)?;
+        events::status(format!("{} Restored {} to {}", "Note:".yellow(), name, old_version));
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Moves each dependency in `packages/requirements.txt` to the newest version
+/// on the Conan remotes, then reinstalls (restoring the file if that fails).
+/// Unpinned packages get pinned; version ranges and non-numeric versions are
+/// left alone.
+fn update_dependencies(dry_run: bool) -> Result<(), std::io::Error> {
+    let requirements_path = editable_requirements()?;
+    let content = fs::read_to_string(requirements_path)?;
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+
+    events::status("Checking the Conan remotes for newer versions...".green());
+    let mut updated = 0;
+    for line in lines.iter_mut() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let reference = normalize_requirement(trimmed);
+        let (name, spec) = reference.split_once('/').unwrap_or((&reference, ""));
+        let (current, channel) = match spec.split_once('@') {
+            Some((current, channel)) => (current, format!("@{}", channel)),
+            None => (spec, String::new()),
+        };
+        if current.starts_with('[') {
+            events::status(format!("  {} {} {}", name, current, "is a version range, leaving it as is".dimmed()));
+            continue;
+        }
+        let latest = match deps::latest_version(name) {
+            Ok(Some(latest)) => latest,
+            Ok(None) => {
+                events::status(format!("  {} {} {}", name, current, "not found on any remote, leaving it as is".yellow()));
+                continue;
+            }
+            Err(e) => {
+                events::status(format!("  {} {} {}", name, current, e.to_string().red()));
+                continue;
+            }
+        };
+        match version::Version::parse(current) {
+            Some(version) if version >= latest => continue,
+            None if !current.is_empty() => {
+                events::status(format!("  {} {} {}", name, current, format!("can't be compared with {}, leaving it as is", latest).dimmed()));
+                continue;
+            }
+            _ => {}
+        }
+        events::status(format!("  {} {} -> {}", name, if current.is_empty() { "(unpinned)" } else { current }, latest.to_string().green()));
+        *line = format!("{}/{}{}", name, latest, channel);
+        updated += 1;
+    }
+
+    if updated == 0 {
+        events::status(format!("{} All dependencies are up to date.", "Success:".green()));
+        return Ok(());
+    }
+    if dry_run {
+        events::status(format!("{} Dry run, {} was not modified.", "Note:".yellow(), REQUIREMENTS_FILE));
+        return Ok(());
+    }
+
+    fs::write(requirements_path, lines.join("\n") + "\n")?;
+    events::status(format!("{} {} dependencies in {}", "Updated".green(), updated, REQUIREMENTS_FILE));
+    if let Err(e) = install_dependencies(&InstallArgs::default()) {
+        fs::write(requirements_path, content)?;
+        events::status(format!("{} Restored {} to the previous versions", "Note:".yellow(), REQUIREMENTS_FILE));
+        return Err(e);
+    }
+    Ok(())
+}
+
+fn install_dependencies(args: &InstallArgs) -> Result<(), std::io::Error> {
+    if backend::is_meson_project(Path::new(".")) {
+        return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Dependency installation is not supported for Meson projects yet."));
+    }
+
+    events::status("Installing dependencies...".green());
+
+    // 1. Read the dependencies: --requirements, sage.toml or requirements.txt
+    let dependencies = match &args.requirements {
+        Some(requirements_path) => read_requirements(requirements_path)?,
+        None => project_dependencies()?,
+    };
+
+    // Even without dependencies Conan has to run: compile configures with
+    // the conan_toolchain.cmake it generates.
+    if dependencies.is_empty() {
+        events::status("No dependencies to install, generating the Conan toolchain only.".dimmed());
+    } else {
+        events::status(format!("Found dependencies: {:?}", dependencies));
+    }
+
+    // 2. Create conanfile.txt
+    let conanfile_path = Path::new("conanfile.txt");
+    write_conanfile(conanfile_path, &dependencies, &args.generators)?;
+
+    // 3. Run conan install
+    let pinned = manifest::load(Path::new("."))?.map(|m| m.install).unwrap_or_default();
+    let mut build_profile = args.profile_build.clone().or(pinned.build_profile);
+    let host_profile = match &args.triple {
+        // The pinned host profile describes native builds; a cross build
+        // needs an explicit build profile, Conan's default one unless pinned.
+        Some(triple) => {
+            build_profile.get_or_insert_with(|| "default".to_string());
+            Some(args.profile.clone().map_or_else(|| triple.host_profile(), Ok)?)
+        }
+        None => args.profile.clone().or(pinned.profile),
+    };
+    let output_folder = args.triple.as_ref().map_or_else(|| "packages/install".to_string(), cross::Triple::install_dir);
+    for profile in host_profile.iter().chain(build_profile.iter()) {
+        check_conan_profile(profile);
+    }
+    // Once a snapshot exists it is refreshed on every install.
+    let snapshot_path = args.lock_out.clone().or_else(|| Some(PathBuf::from(deps::RESOLVED_FILE)).filter(|path| path.is_file()));
+    events::status("Running conan install...".green());
+    let mut command = Command::new("conan");
+    command
+        .args(["install", ".", &format!("--build={}", args.build_policy), &format!("--output-folder={}", output_folder)])
+        .args(host_profile.map(|profile| format!("--profile:host={}", profile)))
+        .args(build_profile.map(|profile| format!("--profile:build={}", profile)))
+        .args(snapshot_path.as_ref().map(|_| "--format=json"));
+    events::command(&command);
+    let output = command.output()?;
+
+    // 4. Delete conanfile.txt
+    fs::remove_file(conanfile_path)?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(std::io::Error::other(format!("Conan install failed:\n{}", stderr)));
+    }
+    match &snapshot_path {
+        Some(path) => match serde_json::from_slice(&output.stdout) {
+            Ok(graph) => {
+                let count = deps::write_snapshot(&graph, path)?;
+                events::status(format!("{} Wrote {} resolved package(s) to {}", "Success:".green(), count, path.display()));
+            }
+            Err(e) => events::status(format!("{} Could not parse Conan's graph output, {} was not updated: {}", "Warning:".yellow(), path.display(), e)),
+        },
+        None => {
+            if events::show_tool_output(false) {
+                println!("{}", String::from_utf8_lossy(&output.stdout));
+            }
+        }
+    }
+
+
+    // 5. Update CMakeLists.txt
+    update_dependency_block(&dependencies)
+}
+
+/// Regenerates the `find_package`/`target_link_libraries` lines between the
+/// dependency markers of the project's CMakeLists.txt from `dependencies`.
+fn update_dependency_block(dependencies: &[String]) -> Result<(), std::io::Error> {
+    events::status("Updating CMakeLists.txt...".green());
+    let project_name = env::current_dir()?.file_name().unwrap().to_str().unwrap().to_string();
+    let cmake_path = Path::new(&project_name).join("CMakeLists.txt");
+    
+    let mut cmake_content = fs::read_to_string(&cmake_path)?;
+
+    let new_deps = dependency_lines(&project_name, dependencies);
+
+    let markers = manifest::load(Path::new("."))?.map(|m| m.markers).unwrap_or_default();
+    let start_marker = markers.dependencies_start.as_str();
+    let end_marker = markers.dependencies_end.as_str();
+
+    let start = cmake_content.find(start_marker).map(|start| start + start_marker.len());
+    let end = start.and_then(|start| cmake_content[start..].find(end_marker).map(|end| start + end));
+    if let (Some(start), Some(end)) = (start, end) {
+        let range = start..end;
+        let block = if new_deps.is_empty() { "\n".to_string() } else { format!("\n{}\n", new_deps) };
+        cmake_content.replace_range(range, &block);
+        fs::write(&cmake_path, cmake_content)?;
+        events::status(format!("{} Successfully updated CMakeLists.txt", "Success:".green()));
+    } else {
+        return Err(std::io::Error::other(format!("Could not find the dependency markers '{}' and '{}' in {}", start_marker, end_marker, cmake_path.display())));
+    }
+
+    Ok(())
+}
+
+/// The `find_package`/`target_link_libraries` lines linking `dependencies`
+/// (Conan references) into `project_name`'s target.
+fn dependency_lines(project_name: &str, dependencies: &[String]) -> String {
+    let mut lines = String::new();
+    for dep in dependencies {
+        let dep_name = dep.split('/').next().unwrap();
+        lines.push_str(&format!("find_package({})\n", dep_name));
+        lines.push_str(&format!("target_link_libraries({} PRIVATE {}::{})\n", project_name, dep_name, dep_name));
+    }
+    lines
+}
+
+/// Removes `package` (matched by name, any version) from
+/// `packages/requirements.txt` and drops its lines from the CMakeLists
+/// dependency block, so no dangling `target_link_libraries` is left behind.
+fn remove_dependency(package: &str) -> Result<(), std::io::Error> {
+    let name = normalize_requirement(package.trim());
+    let name = name.split('/').next().unwrap_or_default();
+
+    let requirements_path = editable_requirements()?;
+    let content = fs::read_to_string(requirements_path)?;
+    let is_package = |line: &str| {
+        let line = line.trim();
+        !line.starts_with('#') && normalize_requirement(line).split('/').next() == Some(name)
+    };
+    let (removed, kept): (Vec<&str>, Vec<&str>) = content.lines().partition(|line| is_package(line));
+    if removed.is_empty() {
+        events::status(format!("{} '{}' is not listed in {}", "Warning:".yellow(), name, REQUIREMENTS_FILE));
+        return Ok(());
+    }
+    fs::write(requirements_path, kept.join("\n") + "\n")?;
+    for line in removed {
+        events::status(format!("{} {} from {}", "Removed".green(), line.trim(), REQUIREMENTS_FILE));
+    }
+
+    update_dependency_block(&read_requirements(requirements_path)?)
+}
+
+
+/// Warns when a Conan profile isn't listed by `conan profile list`.
+fn check_conan_profile(profile: &str) {
+    let Ok(output) = Command::new("conan").args(["profile", "list"]).output() else {
+        return;
+    };
+    let listed = String::from_utf8_lossy(&output.stdout);
+    // A path to a profile file is fine too, Conan accepts those directly.
+    if !listed.lines().any(|line| line.trim() == profile) && !Path::new(profile).is_file() {
+        events::status(format!("{} Conan profile '{}' was not found (see 'conan profile list')", "Warning:".yellow(), profile));
+    }
+}
+
+/// Parses `--std`, accepting `20` as well as `c++20`.
+fn parse_cpp_std(value: &str) -> Result<u32, String> {
+    let version = value.trim().to_ascii_lowercase();
+    let version = version.strip_prefix("c++").unwrap_or(&version);
+    match version.parse() {
+        Ok(version) if manifest::CPP_STANDARDS.contains(&version) => Ok(version),
+        _ => Err(format!("unsupported C++ standard '{}', expected one of: {}", value, manifest::CPP_STANDARDS.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", "))),
+    }
+}
+
+/// Derives a name that is safe to use as a CMake project/target identifier and
+/// directory name from a human-friendly display name, e.g. "My App" -> "My_App".
+fn cmake_identifier(display_name: &str) -> String {
+    let mut identifier: String = display_name
+        .trim()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .collect();
+    if identifier.starts_with(|c: char| c.is_ascii_digit()) {
+        identifier.insert(0, '_');
+    }
+    identifier
+}
+
+/// Appends `file` to the source list of `target`'s `add_executable`/`add_library`
+/// call, so new files get compiled without hand-editing CMake.
+fn add_source(file: &Path, target: Option<&str>) -> Result<(), std::io::Error> {
+    let project_name = env::current_dir()?.file_name().unwrap().to_str().unwrap().to_string();
+    let target = target.unwrap_or(&project_name);
+
+    // A bare file name goes into the project's source directory.
+    let layout = manifest::load(Path::new("."))?.map(|m| m.layout).unwrap_or_default();
+    let resolved;
+    let file = if file.parent().is_some_and(|p| p.as_os_str().is_empty()) {
+        layout.validate(Path::new(&project_name))?;
+        resolved = Path::new(&project_name).join(&layout.source_dir).join(file);
+        resolved.as_path()
+    } else {
+        file
+    };
+
+    if !file.exists() {
+        events::status(format!("{} {} does not exist yet", "Warning:".yellow(), file.display()));
+    }
+    if file.extension().is_some_and(|extension| extension == "mm") {
+        enable_objcxx(Path::new("CMakeLists.txt"))?;
+    }
+
+    for cmake_path in cmake::cmake_lists_files(Path::new(".")) {
+        let mut content = fs::read_to_string(&cmake_path)?;
+        let Some(span) = cmake::target_arguments_span(&content, target) else {
+            continue;
+        };
+
+        // Paths in a CMakeLists are relative to its own directory.
+        let cmake_dir = cmake_path.parent().unwrap_or(Path::new("."));
+        let source = match file.strip_prefix(cmake_dir.strip_prefix(".").unwrap_or(cmake_dir)) {
+            Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+            Err(_) => format!("${{PROJECT_SOURCE_DIR}}/{}", file.to_string_lossy().replace('\\', "/")),
+        };
+
+        if content[span.clone()].split_whitespace().any(|existing| existing == source) {
+            events::status(format!("{} {} is already part of '{}'", "Note:".yellow(), source, target));
+            return Ok(());
+        }
+
+        // Keep the one-source-per-line layout of the generated CMakeLists.
+        let arguments = &content[span.clone()];
+        let insertion = match arguments.rfind('\n') {
+            Some(newline) if arguments[newline..].trim().is_empty() => {
+                let indent = arguments.lines().nth(1).map(|l| &l[..l.len() - l.trim_start().len()]).unwrap_or("    ");
+                (span.start + newline, format!("\n{}{}", indent, source))
+            }
+            _ => (span.end, format!(" {}", source)),
+        };
+        content.insert_str(insertion.0, &insertion.1);
+        fs::write(&cmake_path, content)?;
+
+        events::status(format!("{} Added {} to '{}' in {}", "Success:".green(), source, target, cmake_path.display()));
+        return Ok(());
+    }
+
+    Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("No add_executable/add_library call for target '{}' found.", target)))
+}
+
+/// Enables Objective-C++ in the top-level CMakeLists when the first `.mm`
+/// source is added.
+fn enable_objcxx(cmake_path: &Path) -> Result<(), std::io::Error> {
+    let content = fs::read_to_string(cmake_path)?;
+    if content.contains("OBJCXX") {
+        return Ok(());
+    }
+    warn_objcxx_platform();
+    match with_objcxx_language(&content) {
+        Some(updated) => {
+            fs::write(cmake_path, updated)?;
+            events::status(format!("{} Enabled OBJCXX in the project() languages of {}", "Note:".yellow(), cmake_path.display()));
+        }
+        None => events::status(format!("{} Could not find project(... LANGUAGES ...) in {}; add OBJCXX to it for .mm sources", "Warning:".yellow(), cmake_path.display())),
+    }
+    Ok(())
+}
+
+/// `packages/requirements.txt`, for the commands that edit it. Fails when
+/// `sage.toml` declares the dependencies instead, since install would
+/// ignore the edit.
+fn editable_requirements() -> Result<&'static Path, std::io::Error> {
+    if dependencies_source()? == manifest::MANIFEST_FILE {
+        return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, format!("This project declares its dependencies in the [dependencies] table of {}, edit them there.", manifest::MANIFEST_FILE)));
+    }
+    let requirements_path = Path::new(REQUIREMENTS_FILE);
+    if !requirements_path.is_file() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} not found. Are you in the project root?", REQUIREMENTS_FILE)));
+    }
+    Ok(requirements_path)
+}
+
+/// Where the project declares its dependencies: the `[dependencies]` table of
+/// `sage.toml` when it lists any, `packages/requirements.txt` otherwise.
+fn dependencies_source() -> Result<&'static str, std::io::Error> {
+    let in_manifest = manifest::load(Path::new("."))?.is_some_and(|m| !m.dependencies.is_empty());
+    Ok(if in_manifest { manifest::MANIFEST_FILE } else { REQUIREMENTS_FILE })
+}
+
+/// The project's dependencies as Conan references, read from
+/// `dependencies_source()`.
+fn project_dependencies() -> Result<Vec<String>, std::io::Error> {
+    if dependencies_source()? == REQUIREMENTS_FILE {
+        return read_requirements(Path::new(REQUIREMENTS_FILE));
+    }
+    let requirements = Path::new(REQUIREMENTS_FILE);
+    if requirements.is_file() && !read_requirements(requirements)?.is_empty() {
+        events::status(format!("{} Using [dependencies] from {}, {} is ignored", "Note:".yellow(), manifest::MANIFEST_FILE, REQUIREMENTS_FILE));
+    }
+    Ok(manifest::load(Path::new("."))?.map(|m| m.dependency_references()).unwrap_or_default())
+}
+
+/// Reads the dependency lines from a requirements file (normally
+/// `packages/requirements.txt`), skipping blank lines and `#` comments.
+fn read_requirements(requirements_path: &Path) -> Result<Vec<String>, std::io::Error> {
+    if !requirements_path.is_file() {
+        if requirements_path == Path::new(REQUIREMENTS_FILE) {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} not found. Are you in the project root?", REQUIREMENTS_FILE)));
+        }
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("Requirements file not found: {}", requirements_path.display())));
+    }
+    let file = fs::File::open(requirements_path)?;
+    let reader = BufReader::new(file);
+    Ok(reader
+        .lines()
+        .map_while(Result::ok)
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let normalized = normalize_requirement(&line);
+            if normalized != line {
+                events::status(format!("{} '{}' is not a Conan reference, using '{}'", "Warning:".yellow(), line, normalized));
+            }
+            normalized
+        })
+        .collect())
+}
+
+/// Rewrites pip-style (`fmt==10.2.1`) and npm-style (`fmt@10.2.1`) requirements
+/// into Conan's `name/version` form. Lines that already contain a `/` are Conan
+/// references (including ranges like `fmt/[>=10.0]` and `@user/channel`
+/// suffixes) and are passed through untouched.
+fn normalize_requirement(requirement: &str) -> String {
+    if requirement.contains('/') {
+        return requirement.to_string();
+    }
+    for separator in ["==", "@"] {
+        if let Some((name, version)) = requirement.split_once(separator) {
+            return format!("{}/{}", name.trim(), version.trim());
+        }
+    }
+    requirement.to_string()
+}
+
+/// Writes the temporary `conanfile.txt` that Conan commands are run against,
+/// with the default generators plus `extra_generators`.
+fn write_conanfile(path: &Path, dependencies: &[String], extra_generators: &[String]) -> Result<(), std::io::Error> {
+    let mut conanfile_content = "[requires]\n".to_string();
+    for dep in dependencies {
+        conanfile_content.push_str(dep);
+        conanfile_content.push('\n');
+    }
+    conanfile_content.push_str("\n[generators]\n");
+    let mut generators: Vec<&str> = DEFAULT_CONAN_GENERATORS.to_vec();
+    for generator in extra_generators {
+        if !generators.contains(&generator.as_str()) {
+            generators.push(generator);
+        }
+    }
+    for generator in generators {
+        conanfile_content.push_str(generator);
+        conanfile_content.push('\n');
+    }
+    fs::write(path, conanfile_content)
+}
+
+/// Deletes the project at `root` for `new --overwrite`, after confirmation.
+/// Directories that don't look like a sage project are never touched.
+fn remove_existing_project(root: &Path, project_name: &str, yes: bool) -> Result<(), std::io::Error> {
+    let markers = manifest::load(root).ok().flatten().map(|m| m.markers).unwrap_or_default();
+    let has_markers = fs::read_to_string(root.join(project_name).join("CMakeLists.txt")).is_ok_and(|content| content.contains(&markers.dependencies_start));
+    let is_meson_project = fs::read_to_string(root.join("meson.build")).is_ok_and(|content| content.contains(&format!("project('{}'", project_name)));
+    let is_sage_project = root.join(manifest::MANIFEST_FILE).is_file() || root.join(REQUIREMENTS_FILE).is_file() || has_markers || is_meson_project;
+    if !root.is_dir() || !is_sage_project {
+        return Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, format!("'{}' exists but doesn't look like a sage project, refusing to overwrite it.", project_name)));
+    }
+
+    println!("{} '{}' and everything in it will be deleted and recreated.", "Warning:".yellow(), project_name);
+    if !yes && !confirm("Refusing to delete the existing project without confirmation. Pass --yes to proceed.")? {
+        return Err(std::io::Error::new(std::io::ErrorKind::Interrupted, format!("Aborted, '{}' was left untouched.", project_name)));
+    }
+    fs::remove_dir_all(root)
+}
+
+fn create_project(args: &NewArgs) -> Result<(), std::io::Error> {
+    let display_name = args.name.as_str();
+    let project_name = &cmake_identifier(display_name);
+    if project_name.trim_matches('_').is_empty() {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("'{}' is not a usable project name.", display_name)));
+    }
+    if project_name != display_name {
+        events::status(format!("{} Using '{}' as the CMake project name for '{}'", "Note:".yellow(), project_name, display_name));
+    }
+
+    let root = Path::new(project_name);
+    if root.exists() && !args.overwrite {
+        return Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, format!("Directory '{}' already exists. Pass --overwrite to recreate it.", project_name)));
+    }
+    if (args.lib || args.split) && args.build_system != BuildSystem::Cmake {
+        return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "--lib and --split are only supported for CMake projects."));
+    }
+    if args.with_logging.is_some() && args.build_system != BuildSystem::Cmake {
+        return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "--with-logging needs the Conan integration, which only CMake projects have."));
+    }
+    if args.lang == Language::Objcxx && args.build_system != BuildSystem::Cmake {
+        return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "--lang objcxx is only supported for CMake projects."));
+    }
+    if args.cmake_style == CmakeStyle::Modern && args.build_system != BuildSystem::Cmake {
+        return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "--cmake-style modern is only supported for CMake projects."));
+    }
+    if root.exists() {
+        remove_existing_project(root, project_name, args.yes)?;
+    }
+
+    // Create directory structure
+    fs::create_dir_all(root.join("build/windows"))?;
+    let layout = manifest::Layout { source_dir: args.source_dir.clone(), include_dir: args.include_dir.clone() };
+    let target_dir = root.join(project_name);
+    fs::create_dir_all(target_dir.join(&layout.include_dir))?;
+    fs::create_dir_all(target_dir.join(&layout.source_dir))?;
+    fs::create_dir_all(root.join("install"))?;
+    fs::create_dir_all(root.join("res"))?;
+
+    // Create files
+    if !args.minimal {
+        fs::write(root.join(".clang-format"), CLANG_FORMAT_CONTENT)?;
+        fs::write(root.join(".clang-tidy"), "")?; // Empty file
+        fs::write(root.join(".clangd"), clangd_config(args.cpp_std))?;
+        fs::write(root.join(".editorconfig"), EDITORCONFIG_CONTENT)?;
+        fs::write(root.join(".gitignore"), GITIGNORE_CONTENT)?;
+    }
+    if !args.git_ignore_extra.is_empty() {
+        append_gitignore(&root.join(".gitignore"), &args.git_ignore_extra)?;
+    }
+    if args.lib || args.split {
+        let include_dir = target_dir.join(&layout.include_dir).join(project_name);
+        fs::create_dir_all(&include_dir)?;
+        fs::write(include_dir.join(format!("{}.hpp", project_name)), library_header(project_name))?;
+        fs::write(target_dir.join(&layout.source_dir).join(format!("{}.cpp", project_name)), library_source(project_name))?;
+        if args.split {
+            fs::create_dir_all(target_dir.join(SPLIT_APP_DIR))?;
+            fs::write(target_dir.join(SPLIT_APP_DIR).join("main.cpp"), split_main_cpp(project_name))?;
+        }
+    } else if let Some(library) = args.with_logging {
+        fs::write(target_dir.join(&layout.include_dir).join("log.hpp"), library.header(project_name))?;
+        fs::write(target_dir.join(&layout.source_dir).join("main.cpp"), library.main_cpp())?;
+    } else {
+        fs::write(target_dir.join(&layout.source_dir).join("main.cpp"), MAIN_CPP_CONTENT)?;
+    }
+    if layout != manifest::Layout::default() {
+        manifest::set_value(root, "layout", "source_dir", &layout.source_dir)?;
+        manifest::set_value(root, "layout", "include_dir", &layout.include_dir)?;
+    }
+
+    match args.build_system {
+        BuildSystem::Cmake => {
+            fs::create_dir_all(root.join("cmake"))?;
+            fs::create_dir_all(root.join("packages"))?;
+            let markers = manifest::Markers::default();
+            let (mut top_cmake, mut sub_cmake) = match (args.cmake_style, args.lib || args.split) {
+                (CmakeStyle::Classic, false) => (cmake_lists_top(project_name, display_name, args.cpp_std), cmake_lists_sub(project_name, &layout, &markers)),
+                (CmakeStyle::Classic, true) => (cmake_lists_top(project_name, display_name, args.cpp_std), cmake_lists_sub_lib(project_name, &layout, &markers)),
+                (CmakeStyle::Modern, false) => (cmake_lists_top_modern(project_name, display_name), cmake_lists_sub_modern(project_name, &layout, &markers, args.cpp_std)),
+                (CmakeStyle::Modern, true) => (cmake_lists_top_modern(project_name, display_name), cmake_lists_sub_lib_modern(project_name, &layout, &markers, args.cpp_std)),
+            };
+            if args.lang == Language::Objcxx {
+                warn_objcxx_platform();
+                top_cmake = with_objcxx_language(&top_cmake).unwrap_or(top_cmake);
+            }
+            fs::write(root.join("CMakeLists.txt"), top_cmake)?;
+            fs::write(root.join("cmake/config.cmake"), CONFIG_CMAKE_CONTENT)?;
+            fs::write(root.join(cmake::PRESETS_FILE), cmake_presets(default_generator()))?;
+            if args.split {
+                sub_cmake.push_str(&cmake_split_app(project_name, args.cmake_style, args.cpp_std));
+            }
+            if args.lib && !args.no_install_rules {
+                sub_cmake.push_str(&cmake_install_rules(project_name, &layout));
+                fs::write(root.join("cmake").join(format!("{}Config.cmake.in", project_name)), package_config_in(project_name))?;
+            }
+            let mut requirements = REQUIREMENTS_TXT_CONTENT.to_string();
+            if let Some(library) = args.with_logging {
+                let dependencies = [library.requirement().to_string()];
+                let empty_block = format!("{}\n{}", markers.dependencies_start, markers.dependencies_end);
+                let block = format!("{}\n{}\n{}", markers.dependencies_start, dependency_lines(project_name, &dependencies), markers.dependencies_end);
+                sub_cmake = sub_cmake.replacen(&empty_block, &block, 1);
+                requirements.push_str(&dependencies[0]);
+                requirements.push('\n');
+            }
+            fs::write(root.join(project_name).join("CMakeLists.txt"), sub_cmake)?;
+            fs::write(root.join(REQUIREMENTS_FILE), requirements)?;
+        }
+        // Meson projects don't get the Conan integration (yet), so no packages/.
+        BuildSystem::Meson => {
+            fs::write(root.join("meson.build"), meson_build(project_name, &layout, args.cpp_std))?;
+        }
+    }
+
+    if args.pre_commit {
+        write_if_missing(&root.join(".pre-commit-config.yaml"), PRE_COMMIT_CONFIG_CONTENT)?;
+    }
+    if args.docker {
+        docker::write_dockerfile(root, project_name, docker::DEFAULT_BASE_IMAGE)?;
+    }
+    if let Some(profile) = &args.profile_conan {
+        manifest::set_value(root, "install", "profile", profile)?;
+    }
+
+    Ok(())
+}
+
+/// Runs `git init` in the new project at `root` and commits the generated
+/// files. Problems only warn: the project itself was created fine.
+fn init_git_repository(root: &Path) {
+    if find_program("git").is_none() {
+        events::status(format!("{} git not found in PATH, skipping the repository setup", "Warning:".yellow()));
+        return;
+    }
+    // A project created inside another repository belongs to that one.
+    let parent = root.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    if Command::new("git").current_dir(parent).args(["rev-parse", "--is-inside-work-tree"]).output().is_ok_and(|output| output.status.success()) {
+        events::status(format!("{} Already inside a git repository, not creating another one", "Note:".yellow()));
+        return;
+    }
+
+    let steps: [&[&str]; 3] = [&["init", "--quiet"], &["add", "-A"], &["commit", "--quiet", "-m", "Initial commit"]];
+    for step in steps {
+        let output = Command::new("git").current_dir(root).args(step).output();
+        match output {
+            Ok(output) if output.status.success() => {}
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                events::status(format!("{} git {} failed: {}", "Warning:".yellow(), step[0], stderr.lines().next().unwrap_or("").trim()));
+                if step[0] == "commit" {
+                    events::status(format!("  {}", "Set your identity with: git config --global user.name/user.email, then commit".cyan()));
+                }
+                return;
+            }
+            Err(e) => {
+                events::status(format!("{} Could not run git {}: {}", "Warning:".yellow(), step[0], e));
+                return;
+            }
+        }
     }
-    println!("{}", String::from_utf8_lossy(&configure_output.stdout));
-    println!("{}", String::from_utf8_lossy(&configure_output.stderr));
+    events::status(format!("{} Initialized a git repository with an initial commit", "Note:".yellow()));
+}
 
+/// Scaffolds the default CMake project into the current directory, for
+/// adopting cppsage in an existing codebase. Files that already exist are
+/// skipped unless `force` is given; the starter `main.cpp` never replaces one.
+fn init_project(force: bool) -> Result<(), std::io::Error> {
+    let dir_name = env::current_dir()?.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let project_name = &cmake_identifier(&dir_name);
+    if project_name.trim_matches('_').is_empty() {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("'{}' is not a usable project name.", dir_name)));
+    }
+    events::status(format!("{} {} '{}' in the current directory", "Initializing project:".green(), "sage".bold(), project_name.bold()));
 
-    println!("{}", "Compiling project with CMake...".green());
-    // Build with CMake
-    let build_output = Command::new("cmake")
-        .args(&["--build", build_dir])
-        .output()?;
+    let layout = manifest::Layout::default();
+    let markers = manifest::Markers::default();
+    let target_dir = Path::new(project_name);
+    for dir in [Path::new("cmake"), Path::new("packages"), &target_dir.join(&layout.include_dir), &target_dir.join(&layout.source_dir)] {
+        fs::create_dir_all(dir)?;
+    }
 
-    if !build_output.status.success() {
-        let stderr = String::from_utf8_lossy(&build_output.stderr);
-        return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("CMake build failed:\n{}", stderr)));
+    let files = [
+        (PathBuf::from("CMakeLists.txt"), cmake_lists_top(project_name, &dir_name, 17)),
+        (PathBuf::from("cmake/config.cmake"), CONFIG_CMAKE_CONTENT.to_string()),
+        (PathBuf::from(cmake::PRESETS_FILE), cmake_presets(default_generator())),
+        (PathBuf::from(REQUIREMENTS_FILE), REQUIREMENTS_TXT_CONTENT.to_string()),
+        (target_dir.join("CMakeLists.txt"), cmake_lists_sub(project_name, &layout, &markers)),
+        (PathBuf::from(".clang-format"), CLANG_FORMAT_CONTENT.to_string()),
+        (PathBuf::from(".clang-tidy"), String::new()),
+        (PathBuf::from(".clangd"), clangd_config(17)),
+        (PathBuf::from(".editorconfig"), EDITORCONFIG_CONTENT.to_string()),
+        (PathBuf::from(".gitignore"), GITIGNORE_CONTENT.to_string()),
+    ];
+    for (path, content) in &files {
+        if force && path.exists() {
+            fs::write(path, content)?;
+            events::status(format!("{} Overwrote {}", "Note:".yellow(), path.display()));
+        } else {
+            write_if_missing(path, content)?;
+        }
     }
-    println!("{}", String::from_utf8_lossy(&build_output.stdout));
-     println!("{}", String::from_utf8_lossy(&build_output.stderr));
-    
-    println!("{} Project compiled successfully!", "Success:".green());
+    write_if_missing(&target_dir.join(&layout.source_dir).join("main.cpp"), MAIN_CPP_CONTENT)?;
 
+    events::status(format!("{} Project '{}' initialized.", "Success:".green(), project_name));
+    events::status(format!("  {}", format!("List existing sources in {}/CMakeLists.txt, e.g. with: sage add-source <file>", project_name).cyan()));
     Ok(())
 }
 
-fn run_project() -> Result<(), std::io::Error> {
-    // First, compile the project
-    compile_project()?;
-
-    println!("{}", "Running project...".green());
-
-    let project_name = env::current_dir()?.file_name().unwrap().to_str().unwrap().to_string();
-    
-    let exe_path = if cfg!(target_os = "windows") {
-        Path::new("build").join(&project_name).join(format!("{}.exe", project_name))
-    } else {
-        Path::new("build").join(&project_name).join(&project_name)
+/// Recursively collects files under `dir` whose extension is in `extensions`,
+/// skipping hidden directories and the generated `build`/`packages` trees.
+fn collect_sources(dir: &Path, extensions: &[&str]) -> Vec<PathBuf> {
+    let mut sources = Vec::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return sources;
     };
-
-    if !exe_path.exists() {
-        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("Executable not found at: {:?}", exe_path)));
+    for entry in entries.map_while(Result::ok) {
+        let path = entry.path();
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        if path.is_dir() {
+            if file_name.starts_with('.') || file_name == "build" || file_name == "packages" {
+                continue;
+            }
+            sources.extend(collect_sources(&path, extensions));
+        } else if path.extension().and_then(|e| e.to_str()).is_some_and(|e| extensions.contains(&e)) {
+            sources.push(path);
+        }
     }
+    sources.sort();
+    sources
+}
 
-    let run_output = Command::new(exe_path).output()?;
-
-    println!("--- Program Output ---");
-    println!("{}", String::from_utf8_lossy(&run_output.stdout));
-    eprintln!("{}", String::from_utf8_lossy(&run_output.stderr));
-    println!("--- End Program Output ---");
-
-    if !run_output.status.success() {
-        return Err(std::io::Error::new(std::io::ErrorKind::Other, "Project execution failed."));
+/// Appends `patterns` to the `.gitignore` at `path` (creating it if needed),
+/// skipping patterns that are already listed.
+fn append_gitignore(path: &Path, patterns: &[String]) -> Result<(), std::io::Error> {
+    let mut content = if path.exists() { fs::read_to_string(path)? } else { String::new() };
+    let mut added = Vec::new();
+    for pattern in patterns.iter().map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        if content.lines().any(|line| line.trim() == pattern) {
+            events::status(format!("{} '{}' is already in {}", "Note:".yellow(), pattern, path.display()));
+            continue;
+        }
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(pattern);
+        content.push('\n');
+        added.push(pattern);
     }
-
+    if added.is_empty() {
+        return Ok(());
+    }
+    fs::write(path, content)?;
+    events::status(format!("{} Added {} to {}", "Success:".green(), added.join(", "), path.display()));
     Ok(())
 }
 
+/// Writes `content` to `path` unless the file already exists, so optional
+/// scaffolding never clobbers files the user already has.
+fn write_if_missing(path: &Path, content: &str) -> Result<(), std::io::Error> {
+    if path.exists() {
+        events::status(format!("{} {} already exists, skipping", "Note:".yellow(), path.display()));
+        return Ok(());
+    }
+    fs::write(path, content)
+}
 
-fn install_dependencies() -> Result<(), std::io::Error> {
-    println!("{}", "Installing dependencies...".green());
+/// A tool `sage doctor` checks for, and how the generated Dockerfile gets it.
+struct RequiredTool {
+    name: &'static str,
+    install_hint: &'static str,
+    /// The Debian/Ubuntu package providing it, or `None` when the Dockerfile
+    /// installs it another way (Conan comes from pip).
+    apt_package: Option<&'static str>,
+}
 
-    // 1. Parse requirements.txt
-    let requirements_path = Path::new("packages/requirements.txt");
-    if !requirements_path.exists() {
-        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "packages/requirements.txt not found. Are you in the project root?"));
-    }
-    let file = fs::File::open(requirements_path)?;
-    let reader = BufReader::new(file);
-    let dependencies: Vec<String> = reader
-        .lines()
-        .filter_map(Result::ok)
-        .map(|line| line.trim().to_string())
-        .filter(|line| !line.is_empty() && !line.starts_with('#'))
-        .collect();
+/// The tools every sage workflow needs. Doctor checks exactly this list and
+/// the Dockerfile installs it, so the two can't drift apart.
+const REQUIRED_TOOLS: &[RequiredTool] = &[
+    RequiredTool { name: "cmake", install_hint: "winget install Kitware.CMake", apt_package: Some("cmake") },
+    RequiredTool { name: "ninja", install_hint: "winget install Kitware.Ninja", apt_package: Some("ninja-build") },
+    RequiredTool { name: "conan", install_hint: "pip install conan", apt_package: None },
+    RequiredTool { name: "clang", install_hint: "winget install LLVM.LLVM", apt_package: Some("clang") },
+];
 
-    if dependencies.is_empty() {
-        println!("{}", "No dependencies to install.".yellow());
-        return Ok(());
+fn check_tools() {
+    println!("\n{}", "cppsage doctor".bold().underline());
+    if buildenv::conan_build_env().is_some() {
+        println!("{}", "Using the Conan build environment from packages/install (tool_requires)".dimmed());
+    }
+    for tool in REQUIRED_TOOLS {
+        check_tool(&tool_status(tool));
     }
-    
-    println!("Found dependencies: {:?}", dependencies);
 
-    // 2. Create conanfile.txt
-    let conanfile_path = Path::new("conanfile.txt");
-    let mut conanfile_content = "[requires]\n".to_string();
-    for dep in &dependencies {
-        conanfile_content.push_str(dep);
-        conanfile_content.push('\n');
+    if cfg!(target_os = "windows") {
+        check_vs_build_tools();
+        check_long_paths();
     }
-    conanfile_content.push_str("\n[generators]\n");
-    conanfile_content.push_str("CMakeDeps\n");
-    conanfile_content.push_str("CMakeToolchain\n");
-    fs::write(conanfile_path, conanfile_content)?;
 
-    // 3. Run conan install
-    println!("{}", "Running conan install...".green());
-    let output = Command::new("conan")
-        .args(&["install", ".", "--build=missing", "--output-folder=packages/install"])
-        .output()?;
+    check_environment();
+    terminal::report();
+    check_system_resources();
+    check_required_versions();
+    check_toolchain_file();
+}
 
-    // 4. Delete conanfile.txt
-    fs::remove_file(conanfile_path)?;
+/// Runs sage's own `new`/`compile`/`run` pipeline on a throwaway hello-world
+/// project in a temp dir, catching integration problems (generator
+/// mismatches, broken toolchain files) that the per-tool checks miss.
+fn check_deep() -> Result<(), std::io::Error> {
+    println!("\n{}", "Smoke test".bold().underline());
+    let original_dir = env::current_dir()?;
+    let temp_dir = env::temp_dir().join(format!("sage-doctor-{}", std::process::id()));
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Conan install failed:\n{}", stderr)));
-    }
-    println!("{}", String::from_utf8_lossy(&output.stdout));
+    let result = fs::create_dir_all(&temp_dir)
+        .and_then(|_| env::set_current_dir(&temp_dir))
+        .and_then(|_| check_standard_library())
+        .and_then(|_| smoke_test_stages());
 
+    let _ = env::set_current_dir(&original_dir);
+    let _ = fs::remove_dir_all(&temp_dir);
 
-    // 5. Update CMakeLists.txt
-    println!("{}", "Updating CMakeLists.txt...".green());
-    let project_name = env::current_dir()?.file_name().unwrap().to_str().unwrap().to_string();
-    let cmake_path = Path::new(&project_name).join("CMakeLists.txt");
-    
-    let mut cmake_content = fs::read_to_string(&cmake_path)?;
+    result.map_err(|e| std::io::Error::new(e.kind(), format!("Smoke test failed: {}", e)))?;
+    println!("{} The toolchain builds and runs a project end-to-end.", "Success:".green());
+    Ok(())
+}
 
-    let mut new_deps = String::new();
-    for dep in dependencies {
-        let dep_name = dep.split('/').next().unwrap();
-        new_deps.push_str(&format!("find_package({})\n", dep_name));
-        new_deps.push_str(&format!("target_link_libraries({} PRIVATE {}::{})\n", project_name, dep_name, dep_name));
+/// Compiles and links a program using `<iostream>` and `<vector>` directly with
+/// the C++ compiler. Minimal container images often ship a compiler without
+/// the standard library headers, which `--version` checks can't notice.
+fn check_standard_library() -> Result<(), std::io::Error> {
+    const STDLIB_PROGRAM: &str = "#include <iostream>\n#include <vector>\n\nint main() {\n    std::vector<int> v{1, 2, 3};\n    std::cout << v.size() << std::endl;\n}\n";
+
+    print!("- {}: ", "C++ standard library".bold());
+    fs::write("stdlib_check.cpp", STDLIB_PROGRAM)?;
+    let compiler = compiler::cxx_program();
+    let compile_args: &[&str] = if compiler::detect(Path::new("build")) == compiler::Compiler::Msvc {
+        &["/nologo", "/EHsc", "stdlib_check.cpp", "/Fe:stdlib_check.exe"]
+    } else {
+        &["stdlib_check.cpp", "-o", "stdlib_check"]
+    };
+    let output = buildenv::tool_command(&compiler).args(compile_args).output();
+
+    match output {
+        Ok(output) if output.status.success() => {
+            println!("{}", "OK".green());
+            Ok(())
+        }
+        Ok(output) => {
+            println!("{}", "Missing".red());
+            let hint = if cfg!(target_os = "windows") {
+                "Install the \"Desktop development with C++\" workload of the Visual Studio Build Tools."
+            } else if cfg!(target_os = "macos") {
+                "xcode-select --install"
+            } else {
+                "apt install libstdc++-dev (or build-essential; dnf install libstdc++-devel on Fedora)"
+            };
+            println!("  {}", hint.cyan());
+            let diagnostics = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+            Err(std::io::Error::other(format!("{} could not build a program using <iostream> and <vector>:\n{}", compiler, diagnostics.trim())))
+        }
+        Err(e) => {
+            println!("{}", "Not found".red());
+            Err(std::io::Error::new(e.kind(), format!("Could not run the C++ compiler '{}': {}", compiler, e)))
+        }
     }
+}
 
-    let start_marker = "# cppsage:dependencies_start";
-    let end_marker = "# cppsage:dependencies_end";
+fn smoke_test_stages() -> Result<(), std::io::Error> {
+    const PROJECT: &str = "sage_doctor";
+
+    smoke_test_stage("create", || {
+        create_project(&NewArgs {
+            name: PROJECT.to_string(),
+            pre_commit: false,
+            build_system: BuildSystem::Cmake,
+            lib: false,
+            split: false,
+            cmake_style: CmakeStyle::Classic,
+            lang: Language::Cxx,
+            git: false,
+            no_git: true,
+            git_hooks: false,
+            cpp_std: 17,
+            overwrite: false,
+            yes: false,
+            installable: false,
+            no_install_rules: false,
+            with_logging: None,
+            source_dir: "src".to_string(),
+            include_dir: "include".to_string(),
+            minimal: true,
+            docker: false,
+            profile_conan: None,
+            git_ignore_extra: Vec::new(),
+        })?;
+        env::set_current_dir(PROJECT)
+    })?;
+    smoke_test_stage("install", || install_dependencies(&InstallArgs::default()))?;
+    smoke_test_stage("configure", || configure_project("build", &[], None, None).map(|_| ()))?;
+    smoke_test_stage("build", || compile_project(&CompileArgs::default()))?;
+    smoke_test_stage("run", || {
+        let exe_path = targets::find_executable(Path::new("build"), PROJECT, None)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "The built executable was not found under build/."))?;
+        let output = Command::new(exe_path).output()?;
+        if !output.status.success() || !String::from_utf8_lossy(&output.stdout).contains("Hello, world!") {
+            return Err(std::io::Error::other("The built program did not print the expected output."));
+        }
+        Ok(())
+    })
+}
 
-    if let (Some(start), Some(end)) = (cmake_content.find(start_marker), cmake_content.find(end_marker)) {
-        let range = start + start_marker.len()..end;
-        cmake_content.replace_range(range, &format!("\n{}\n", new_deps));
-        fs::write(&cmake_path, cmake_content)?;
-        println!("{} Successfully updated CMakeLists.txt", "Success:".green());
-    } else {
-        return Err(std::io::Error::new(std::io::ErrorKind::Other, "Could not find dependency markers in CMakeLists.txt"));
+fn smoke_test_stage(stage: &str, run: impl FnOnce() -> Result<(), std::io::Error>) -> Result<(), std::io::Error> {
+    println!("{} {}", "Smoke test stage:".green(), stage.bold());
+    match run() {
+        Ok(()) => {
+            println!("- {}: {}", stage.bold(), "OK".green());
+            Ok(())
+        }
+        Err(e) => {
+            println!("- {}: {}", stage.bold(), "Failed".red());
+            Err(std::io::Error::new(e.kind(), format!("{} stage: {}", stage, e)))
+        }
     }
+}
 
-    Ok(())
+fn check_toolchain_file() {
+    // Only meaningful inside a Conan-enabled project.
+    if !Path::new("packages").is_dir() {
+        return;
+    }
+    println!("\n{}", "Project".bold().underline());
+    print!("- {}: ", "Conan toolchain".bold());
+    let path = Path::new("packages/install/conan_toolchain.cmake");
+    match validate_toolchain_file(path) {
+        Ok(()) => println!("{}", "OK".green()),
+        Err(_) if !path.exists() => {
+            println!("{}", "Not generated".yellow());
+            println!("  {}", "Run 'sage install' to generate it.".cyan());
+        }
+        Err(problem) => {
+            println!("{} {}", "Warning:".yellow(), problem);
+            println!("  {}", "Re-run 'sage install' to regenerate it.".cyan());
+        }
+    }
 }
 
+/// Validates installed tool versions against the `[tools]` requirements the
+/// project declares in `sage.toml`.
+fn check_required_versions() {
+    let manifest = match manifest::load(Path::new(".")) {
+        Ok(Some(manifest)) if !manifest.tools.is_empty() => manifest,
+        Ok(_) => return,
+        Err(e) => {
+            println!("\n{} {}", "Warning:".yellow(), e);
+            return;
+        }
+    };
 
-fn create_project(project_name: &str) -> Result<(), std::io::Error> {
-    let root = Path::new(project_name);
-    if root.exists() {
-        return Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, format!("Directory '{}' already exists.", project_name)));
+    println!("\n{}", "Project tool requirements".bold().underline());
+    for (tool, requirement) in &manifest.tools {
+        print!("- {} {}: ", tool.bold(), requirement);
+        let found = buildenv::tool_command(tool)
+            .arg("--version")
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| version::Version::find_in(&String::from_utf8_lossy(&output.stdout)));
+        match found {
+            Some(found) if requirement.matches(&found) => println!("{} {}", "OK".green(), found.to_string().dimmed()),
+            Some(found) => println!("{} found {}", "Too old or incompatible:".red(), found),
+            None => println!("{}", "Not found".red()),
+        }
     }
+}
 
-    // Create directory structure
-    fs::create_dir_all(root.join("build/windows"))?;
-    fs::create_dir_all(root.join("cmake"))?;
-    fs::create_dir_all(root.join(project_name).join("include"))?;
-    fs::create_dir_all(root.join(project_name).join("src"))?;
-    fs::create_dir_all(root.join("install"))?;
-    fs::create_dir_all(root.join("packages"))?;
-    fs::create_dir_all(root.join("res"))?;
-
-    // Create files
-    fs::write(root.join(".clang-format"), CLANG_FORMAT_CONTENT)?;
-    fs::write(root.join(".clang-tidy"), "")?; // Empty file
-    fs::write(root.join(".clangd"), CLANGD_CONTENT)?;
-    fs::write(root.join(".editorconfig"), EDITORCONFIG_CONTENT)?;
-    fs::write(root.join(".gitignore"), GITIGNORE_CONTENT)?;
-    fs::write(root.join("CMakeLists.txt"), &cmake_lists_top(project_name))?;
-    fs::write(root.join("cmake/config.cmake"), CONFIG_CMAKE_CONTENT)?;
-    fs::write(root.join(project_name).join("CMakeLists.txt"), &cmake_lists_sub(project_name))?;
-    fs::write(root.join(project_name).join("src").join("main.cpp"), MAIN_CPP_CONTENT)?;
-    fs::write(root.join("packages/requirements.txt"), REQUIREMENTS_TXT_CONTENT)?;
+/// Environment variables that change how CMake and Conan build the project.
+const BUILD_ENV_VARS: &[&str] = &[
+    "CC",
+    "CXX",
+    "CFLAGS",
+    "CXXFLAGS",
+    "LDFLAGS",
+    "CMAKE_GENERATOR",
+    "CMAKE_PREFIX_PATH",
+    "CMAKE_TOOLCHAIN_FILE",
+    "VCPKG_ROOT",
+    "CONAN_HOME",
+];
+
+fn check_environment() {
+    println!("\n{}", "Environment".bold().underline());
+    let mut any_set = false;
+    for name in BUILD_ENV_VARS {
+        let Ok(value) = env::var(name) else {
+            continue;
+        };
+        any_set = true;
+        print!("- {}: {}", name.bold(), value);
+
+        // A stray CC/CXX export silently changes the compiler CMake picks up.
+        if *name == "CC" || *name == "CXX" {
+            let program = value.split_whitespace().last().unwrap_or("");
+            if find_program(program).is_none() {
+                println!(" {}", "(not found)".red());
+                println!("  {}", format!("Unset {} or point it at an installed compiler", name).cyan());
+                continue;
+            }
+        }
+        println!();
+    }
+    if !any_set {
+        println!("{}", "No build-related environment variables set.".dimmed());
+    }
+}
 
-    Ok(())
+/// Rough peak memory of one heavy C++ compile job (template-heavy code, LTO).
+const MEMORY_PER_JOB: u64 = 1 << 30;
+
+/// Reports system memory against the number of parallel build jobs. Ninja
+/// runs one job per core by default, and template instantiation or LTO can
+/// exhaust RAM long before the CPU count becomes the limit.
+fn check_system_resources() {
+    println!("\n{}", "System resources".bold().underline());
+    let mut system = sysinfo::System::new();
+    system.refresh_memory();
+    let total = system.total_memory();
+    let available = system.available_memory();
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get() as u64);
+
+    println!("- {}: {}", "CPUs".bold(), cpus);
+    if total == 0 {
+        println!("- {}: {}", "Memory".bold(), "unknown on this platform".dimmed());
+        return;
+    }
+    println!("- {}: {} available of {} total", "Memory".bold(), format_gib(available), format_gib(total));
+
+    let safe_jobs = (available / MEMORY_PER_JOB).max(1);
+    if safe_jobs < cpus {
+        println!(
+            "{} {} parallel jobs may need about {} but only {} is available; out-of-memory build failures are likely",
+            "Warning:".yellow(),
+            cpus,
+            format_gib(cpus * MEMORY_PER_JOB),
+            format_gib(available)
+        );
+        println!("  {}", format!("Limit parallelism with --jobs {} or --link-jobs 1", safe_jobs).cyan());
+    }
 }
 
-fn check_tools() {
-    println!("\n{}", "cppsage doctor".bold().underline());
-    check_tool("cmake", &["--version"], "winget install Kitware.CMake");
-    check_tool("ninja", &["--version"], "winget install Kitware.Ninja");
-    check_tool("conan", &["--version"], "pip install conan");
-    check_tool("clang", &["--version"], "winget install LLVM.LLVM");
+fn format_gib(bytes: u64) -> String {
+    format!("{:.1} GiB", bytes as f64 / (1u64 << 30) as f64)
+}
 
-    if cfg!(target_os = "windows") {
-        check_vs_build_tools();
+/// Resolves `program` the way a shell would: as a path when it contains a
+/// separator, otherwise by searching `PATH` (including `.exe` on Windows).
+fn find_program(program: &str) -> Option<PathBuf> {
+    if program.is_empty() {
+        return None;
     }
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .flat_map(|dir| [dir.join(program), dir.join(format!("{}.exe", program))])
+        .find(|candidate| candidate.is_file())
 }
 
-fn check_tool(tool: &str, args: &[&str], install_hint: &str) {
-    print!("- {}: ", tool.bold());
-    match Command::new(tool).args(args).output() {
-        Ok(output) => {
-            if output.status.success() {
-                let version = String::from_utf8_lossy(&output.stdout).lines().next().unwrap_or("").trim().to_string();
-                println!("{} {}", "OK".green(), version.dimmed());
-            } else {
-                println!("{}", "Not found".red());
-                println!("  {}", install_hint.cyan());
-            }
-        }
-        Err(_) => {
+/// The outcome of checking one required tool, as `sage doctor --json`
+/// reports it.
+#[derive(serde::Serialize)]
+struct ToolStatus {
+    name: &'static str,
+    found: bool,
+    /// The first line of `<tool> --version`.
+    version: Option<String>,
+    /// How to install the tool, when it is missing.
+    hint: Option<&'static str>,
+}
+
+fn tool_status(tool: &RequiredTool) -> ToolStatus {
+    let version = buildenv::tool_command(tool.name)
+        .arg("--version")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).lines().next().unwrap_or("").trim().to_string());
+    ToolStatus { name: tool.name, found: version.is_some(), hint: version.is_none().then_some(tool.install_hint), version }
+}
+
+fn check_tool(status: &ToolStatus) {
+    print!("- {}: ", status.name.bold());
+    match &status.version {
+        Some(version) => println!("{} {}", "OK".green(), version.dimmed()),
+        None => {
             println!("{}", "Not found".red());
-            println!("  {}", install_hint.cyan());
+            println!("  {}", status.hint.unwrap_or_default().cyan());
         }
     }
 }
 
+/// `sage doctor --json`: the required tool checks as one JSON object, with
+/// `ok` false when any tool is missing.
+fn print_tools_json() -> Result<(), std::io::Error> {
+    let tools: Vec<ToolStatus> = REQUIRED_TOOLS.iter().map(tool_status).collect();
+    let report = serde_json::json!({ "ok": tools.iter().all(|tool| tool.found), "tools": tools });
+    println!("{}", serde_json::to_string_pretty(&report).map_err(std::io::Error::other)?);
+    Ok(())
+}
+
 #[cfg(target_os = "windows")]
 fn check_vs_build_tools() {
     print!("- {}: ", "Visual Studio Build Tools".bold());
@@ -307,7 +3066,7 @@ fn check_vs_build_tools() {
     }
 
     let result = Command::new(vswhere_path)
-        .args(&["-latest", "-property", "displayName"])
+        .args(["-latest", "-property", "displayName"])
         .output();
 
     match result {
@@ -332,6 +3091,39 @@ fn check_vs_build_tools() {
     // Do nothing on non-Windows platforms
 }
 
+#[cfg(target_os = "windows")]
+fn check_long_paths() {
+    print!("- {}: ", "Long path support".bold());
+
+    // Deep Conan package paths easily exceed MAX_PATH (260 chars) when this is off.
+    let result = Command::new("reg")
+        .args(["query", r"HKLM\SYSTEM\CurrentControlSet\Control\FileSystem", "/v", "LongPathsEnabled"])
+        .output();
+
+    let enabled = match result {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .find(|line| line.contains("LongPathsEnabled"))
+            .map(|line| line.trim_end().ends_with("0x1"))
+            .unwrap_or(false),
+        _ => false,
+    };
+
+    if enabled {
+        println!("{}", "OK".green());
+    } else {
+        println!("{}", "Warning: disabled".yellow());
+        println!("  Deep Conan/CMake paths may exceed 260 characters and fail to build.");
+        println!("  {}", r#"Enable it (as Administrator): reg add HKLM\SYSTEM\CurrentControlSet\Control\FileSystem /v LongPathsEnabled /t REG_DWORD /d 1 /f"#.cyan());
+        println!("  {}", "or via Group Policy: Computer Configuration > Administrative Templates > System > Filesystem > Enable Win32 long paths".cyan());
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn check_long_paths() {
+    // Long path limits only apply to Windows
+}
+
 
 // Boilerplate content
 const CLANG_FORMAT_CONTENT: &str = r#"
@@ -440,10 +3232,12 @@ TabWidth: 4
 UseTab: Never
 "#;
 
-const CLANGD_CONTENT: &str = r#"
+fn clangd_config(cpp_std: u32) -> String {
+    format!(r#"
 CompileFlags:
-  Add: [-std=c++17]
-"#;
+  Add: [-std=c++{}]
+"#, cpp_std)
+}
 
 const EDITORCONFIG_CONTENT: &str = r#"
 root = true
@@ -477,49 +3271,342 @@ packages/
 *.log
 "#;
 
-fn cmake_lists_top(project_name: &str) -> String {
+fn cmake_lists_top(project_name: &str, display_name: &str, cpp_std: u32) -> String {
+    let description = display_name.replace('\\', "\\\\").replace('"', "\\\"");
     format!(r#"
 cmake_minimum_required(VERSION 3.15)
 
 # Conan package management
 include(cmake/config.cmake)
 
-project({} VERSION 0.1.0 LANGUAGES CXX)
+project({0} VERSION 0.1.0 DESCRIPTION "{1}" LANGUAGES CXX)
 
-set(CMAKE_CXX_STANDARD 17)
+set(CMAKE_CXX_STANDARD {2})
 set(CMAKE_CXX_STANDARD_REQUIRED ON)
 
-add_subdirectory({})
-"#, project_name, project_name)
+add_subdirectory({0})
+"#, project_name, description, cpp_std)
+}
+
+/// Adds OBJCXX to the top-level `project()` languages, for `.mm` sources,
+/// and raises `cmake_minimum_required` to 3.16, the first CMake that knows
+/// Objective-C++. `None` when it is already enabled or `project()` has no
+/// single-line `LANGUAGES` list to extend.
+fn with_objcxx_language(cmake: &str) -> Option<String> {
+    let mut changed = false;
+    let lines: Vec<String> = cmake
+        .lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("project(") && trimmed.contains(" LANGUAGES ") && trimmed.trim_end().ends_with(')') && !trimmed.contains("OBJCXX") {
+                changed = true;
+                format!("{} OBJCXX)", line.trim_end().strip_suffix(')').unwrap_or(line))
+            } else if trimmed == "cmake_minimum_required(VERSION 3.15)" {
+                line.replace("3.15", "3.16")
+            } else {
+                line.to_string()
+            }
+        })
+        .collect();
+    changed.then(|| lines.join("\n") + if cmake.ends_with('\n') { "\n" } else { "" })
+}
+
+/// Objective-C++ needs Apple's toolchain in practice; elsewhere CMake only
+/// enables OBJCXX with a GNUstep-style setup.
+fn warn_objcxx_platform() {
+    if !cfg!(target_os = "macos") {
+        events::status(format!("{} Objective-C++ is meant for Apple platforms; enabling OBJCXX will fail to configure here without an Objective-C++ compiler", "Warning:".yellow()));
+    }
+}
+
+/// The `--cmake-style modern` top-level CMakeLists: no project-wide settings,
+/// and policies up to the tested CMake version opted into explicitly.
+fn cmake_lists_top_modern(project_name: &str, display_name: &str) -> String {
+    let description = display_name.replace('\\', "\\\\").replace('"', "\\\"");
+    format!(r#"
+cmake_minimum_required(VERSION 3.15)
+cmake_policy(VERSION 3.15...3.31)
+
+# Conan package management
+include(cmake/config.cmake)
+
+project({0} VERSION 0.1.0 DESCRIPTION "{1}" LANGUAGES CXX)
+
+add_subdirectory({0})
+"#, project_name, description)
+}
+
+/// Mirrors what `sage compile` passes by hand, so `cmake --preset` (and
+/// IDEs reading the presets) build into the same directories, with the
+/// same `generator` (Ninja, or Makefiles where Ninja isn't installed).
+fn cmake_presets(generator: &str) -> String {
+    format!(r#"{{
+  "version": 3,
+  "cmakeMinimumRequired": {{ "major": 3, "minor": 21, "patch": 0 }},
+  "configurePresets": [
+    {{
+      "name": "base",
+      "hidden": true,
+      "generator": "{}",
+      "toolchainFile": "${{sourceDir}}/packages/install/conan_toolchain.cmake",
+      "cacheVariables": {{ "CMAKE_EXPORT_COMPILE_COMMANDS": "ON" }}
+    }},
+    {{
+      "name": "debug",
+      "inherits": "base",
+      "binaryDir": "${{sourceDir}}/build",
+      "cacheVariables": {{ "CMAKE_BUILD_TYPE": "Debug" }}
+    }},
+    {{
+      "name": "release",
+      "inherits": "base",
+      "binaryDir": "${{sourceDir}}/build/release",
+      "cacheVariables": {{ "CMAKE_BUILD_TYPE": "Release" }}
+    }}
+  ],
+  "buildPresets": [
+    {{ "name": "debug", "configurePreset": "debug" }},
+    {{ "name": "release", "configurePreset": "release" }}
+  ]
+}}
+"#, generator)
 }
 
 const CONFIG_CMAKE_CONTENT: &str = r#"
 # This file is managed by cppsage.
 # Manual edits might be overwritten.
 
-# Check if conan_toolchain.cmake exists
-if(EXISTS "${CMAKE_CURRENT_SOURCE_DIR}/packages/install/conan_toolchain.cmake")
+# sage passes the Conan toolchain as CMAKE_TOOLCHAIN_FILE (a per-triple one
+# when cross-compiling); this covers configuring with plain cmake.
+if(CMAKE_TOOLCHAIN_FILE)
+    # Already loaded.
+elseif(EXISTS "${CMAKE_CURRENT_SOURCE_DIR}/packages/install/conan_toolchain.cmake")
     include("${CMAKE_CURRENT_SOURCE_DIR}/packages/install/conan_toolchain.cmake")
 else()
     message(WARNING "Conan toolchain not found. Run 'sage install' to generate it.")
 endif()
 "#;
 
-fn cmake_lists_sub(project_name: &str) -> String {
+/// Joins a layout directory and a path, treating "." as the directory itself.
+fn layout_join(dir: &str, path: &str) -> String {
+    match (dir.trim_end_matches('/'), path) {
+        ("." | "", path) => path.to_string(),
+        (dir, ".") => dir.to_string(),
+        (dir, path) => format!("{}/{}", dir, path),
+    }
+}
+
+fn cmake_lists_sub(project_name: &str, layout: &manifest::Layout, markers: &manifest::Markers) -> String {
     format!(r#"
 add_executable({0}
-    src/main.cpp
+    {1}
+)
+
+target_include_directories({0} PUBLIC
+    "{2}"
+)
+
+{3}
+{4}
+"#, project_name, layout_join(&layout.source_dir, "main.cpp"), layout_join("${CMAKE_CURRENT_SOURCE_DIR}", &layout.include_dir), markers.dependencies_start, markers.dependencies_end)
+}
+
+fn cmake_lists_sub_lib(project_name: &str, layout: &manifest::Layout, markers: &manifest::Markers) -> String {
+    format!(r#"
+add_library({0}
+    {1}
+)
+add_library({0}::{0} ALIAS {0})
+
+target_include_directories({0} PUBLIC
+    "$<BUILD_INTERFACE:{2}>"
+    "$<INSTALL_INTERFACE:include>"
+)
+
+{3}
+{4}
+"#, project_name, layout_join(&layout.source_dir, &format!("{}.cpp", project_name)), layout_join("${CMAKE_CURRENT_SOURCE_DIR}", &layout.include_dir), markers.dependencies_start, markers.dependencies_end)
+}
+
+fn cmake_lists_sub_modern(project_name: &str, layout: &manifest::Layout, markers: &manifest::Markers, cpp_std: u32) -> String {
+    format!(r#"
+add_executable({0})
+
+target_sources({0} PRIVATE
+    {1}
+)
+
+target_include_directories({0} PRIVATE
+    "{2}"
+)
+
+target_compile_features({0} PRIVATE cxx_std_{5})
+set_target_properties({0} PROPERTIES CXX_EXTENSIONS OFF)
+
+{3}
+{4}
+"#, project_name, layout_join(&layout.source_dir, "main.cpp"), layout_join("${CMAKE_CURRENT_SOURCE_DIR}", &layout.include_dir), markers.dependencies_start, markers.dependencies_end, cpp_std)
+}
+
+fn cmake_lists_sub_lib_modern(project_name: &str, layout: &manifest::Layout, markers: &manifest::Markers, cpp_std: u32) -> String {
+    format!(r#"
+add_library({0})
+add_library({0}::{0} ALIAS {0})
+
+target_sources({0} PRIVATE
+    {1}
 )
 
 target_include_directories({0} PUBLIC
-    "${{CMAKE_CURRENT_SOURCE_DIR}}/include"
+    "$<BUILD_INTERFACE:{2}>"
+    "$<INSTALL_INTERFACE:include>"
+)
+
+# PUBLIC, so consumers are compiled with at least the same standard.
+target_compile_features({0} PUBLIC cxx_std_{5})
+set_target_properties({0} PROPERTIES CXX_EXTENSIONS OFF)
+
+{3}
+{4}
+"#, project_name, layout_join(&layout.source_dir, &format!("{}.cpp", project_name)), layout_join("${CMAKE_CURRENT_SOURCE_DIR}", &layout.include_dir), markers.dependencies_start, markers.dependencies_end, cpp_std)
+}
+
+/// Where `--split` puts the executable wrapper, relative to `<name>/`.
+const SPLIT_APP_DIR: &str = "app";
+
+/// The `--split` executable: a thin `<name>_app` wrapper linking the library.
+/// The library keeps the project's name, so `install` links dependencies and
+/// `add-source` adds files to it.
+fn cmake_split_app(project_name: &str, style: CmakeStyle, cpp_std: u32) -> String {
+    match style {
+        CmakeStyle::Classic => format!(r#"
+# Thin executable wrapper around the {0} library
+add_executable({0}_app
+    {1}/main.cpp
+)
+target_link_libraries({0}_app PRIVATE {0})
+"#, project_name, SPLIT_APP_DIR),
+        CmakeStyle::Modern => format!(r#"
+# Thin executable wrapper around the {0} library
+add_executable({0}_app)
+
+target_sources({0}_app PRIVATE
+    {1}/main.cpp
+)
+
+target_link_libraries({0}_app PRIVATE {0})
+target_compile_features({0}_app PRIVATE cxx_std_{2})
+set_target_properties({0}_app PROPERTIES CXX_EXTENSIONS OFF)
+"#, project_name, SPLIT_APP_DIR, cpp_std),
+    }
+}
+
+/// Install/export rules that make the library consumable with
+/// `find_package({name})` after `cmake --install`.
+fn cmake_install_rules(project_name: &str, layout: &manifest::Layout) -> String {
+    format!(r#"
+# Install and export rules, so downstream projects can find_package({0})
+include(GNUInstallDirs)
+include(CMakePackageConfigHelpers)
+
+install(TARGETS {0}
+    EXPORT {0}Targets
+    ARCHIVE DESTINATION ${{CMAKE_INSTALL_LIBDIR}}
+    LIBRARY DESTINATION ${{CMAKE_INSTALL_LIBDIR}}
+    RUNTIME DESTINATION ${{CMAKE_INSTALL_BINDIR}}
+    INCLUDES DESTINATION ${{CMAKE_INSTALL_INCLUDEDIR}}
+)
+install(DIRECTORY {1} DESTINATION ${{CMAKE_INSTALL_INCLUDEDIR}})
+
+install(EXPORT {0}Targets
+    FILE {0}Targets.cmake
+    NAMESPACE {0}::
+    DESTINATION ${{CMAKE_INSTALL_LIBDIR}}/cmake/{0}
+)
+
+configure_package_config_file(
+    "${{PROJECT_SOURCE_DIR}}/cmake/{0}Config.cmake.in"
+    "${{CMAKE_CURRENT_BINARY_DIR}}/{0}Config.cmake"
+    INSTALL_DESTINATION ${{CMAKE_INSTALL_LIBDIR}}/cmake/{0}
+)
+write_basic_package_version_file(
+    "${{CMAKE_CURRENT_BINARY_DIR}}/{0}ConfigVersion.cmake"
+    VERSION ${{PROJECT_VERSION}}
+    COMPATIBILITY SameMajorVersion
+)
+install(FILES
+    "${{CMAKE_CURRENT_BINARY_DIR}}/{0}Config.cmake"
+    "${{CMAKE_CURRENT_BINARY_DIR}}/{0}ConfigVersion.cmake"
+    DESTINATION ${{CMAKE_INSTALL_LIBDIR}}/cmake/{0}
 )
+"#, project_name, layout_join(&layout.include_dir, project_name))
+}
+
+fn package_config_in(project_name: &str) -> String {
+    format!(r#"@PACKAGE_INIT@
 
-# cppsage:dependencies_start
-# cppsage:dependencies_end
+include(CMakeFindDependencyMacro)
+# Add find_dependency(<pkg>) here for every dependency {0} links publicly.
+
+include("${{CMAKE_CURRENT_LIST_DIR}}/{0}Targets.cmake")
+check_required_components({0})
 "#, project_name)
 }
 
+/// The C++ namespace for a project; identifiers can't contain `-`.
+fn cpp_namespace(project_name: &str) -> String {
+    project_name.replace('-', "_")
+}
+
+fn library_header(project_name: &str) -> String {
+    format!(r#"#pragma once
+
+namespace {0} {{
+
+int add(int a, int b);
+
+}} // namespace {0}
+"#, cpp_namespace(project_name))
+}
+
+fn library_source(project_name: &str) -> String {
+    format!(r#"#include "{0}/{0}.hpp"
+
+namespace {1} {{
+
+int add(int a, int b) {{
+    return a + b;
+}}
+
+}} // namespace {1}
+"#, project_name, cpp_namespace(project_name))
+}
+
+fn split_main_cpp(project_name: &str) -> String {
+    format!(r#"#include "{0}/{0}.hpp"
+
+#include <iostream>
+
+int main() {{
+    std::cout << "2 + 3 = " << {1}::add(2, 3) << std::endl;
+    return 0;
+}}
+"#, project_name, cpp_namespace(project_name))
+}
+
+fn meson_build(project_name: &str, layout: &manifest::Layout, cpp_std: u32) -> String {
+    format!(r#"
+project('{0}', 'cpp',
+  version : '0.1.0',
+  default_options : ['cpp_std=c++{3}'])
+
+executable('{0}',
+  '{1}',
+  include_directories : include_directories('{2}'),
+  install : true)
+"#, project_name, layout_join(project_name, &layout_join(&layout.source_dir, "main.cpp")), layout_join(project_name, &layout.include_dir), cpp_std)
+}
+
 const MAIN_CPP_CONTENT: &str = r#"
 #include <iostream>
 
@@ -532,4 +3619,122 @@ int main() {
 const REQUIREMENTS_TXT_CONTENT: &str = r#"
 # Add your dependencies here
 # e.g. fmt/10.2.1
-"#;
\ No newline at end of file
+"#;
+
+const PRE_COMMIT_CONFIG_CONTENT: &str = r#"
+# Generated by cppsage. See https://pre-commit.com for more information.
+# The hooks call cppsage itself so they honour the project's settings.
+repos:
+  - repo: local
+    hooks:
+      - id: cppsage-format
+        name: cppsage fmt --check
+        entry: cppsage fmt --check
+        language: system
+        pass_filenames: false
+        files: \.(c|cc|cpp|cxx|h|hh|hpp|hxx)$
+      # Opt-in: pre-commit run --hook-stage manual cppsage-tidy
+      - id: cppsage-tidy
+        name: cppsage tidy
+        entry: cppsage tidy
+        language: system
+        pass_filenames: false
+        files: \.(c|cc|cpp|cxx|h|hh|hpp|hxx)$
+        stages: [manual]
+"#;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_requirement_converts_pip_style() {
+        assert_eq!(normalize_requirement("fmt==10.2.1"), "fmt/10.2.1");
+        assert_eq!(normalize_requirement("fmt == 10.2.1"), "fmt/10.2.1");
+    }
+
+    #[test]
+    fn normalize_requirement_converts_npm_style() {
+        assert_eq!(normalize_requirement("fmt@10.2.1"), "fmt/10.2.1");
+    }
+
+    #[test]
+    fn normalize_requirement_passes_conan_references_through() {
+        assert_eq!(normalize_requirement("fmt/10.2.1"), "fmt/10.2.1");
+        assert_eq!(normalize_requirement("fmt/[>=10.0]"), "fmt/[>=10.0]");
+        assert_eq!(normalize_requirement("fmt/[>=10.0 <11]"), "fmt/[>=10.0 <11]");
+    }
+
+    #[test]
+    fn normalize_requirement_keeps_user_channel() {
+        assert_eq!(normalize_requirement("fmt/10.2.1@user/stable"), "fmt/10.2.1@user/stable");
+    }
+
+    #[test]
+    fn normalize_requirement_leaves_bare_names_alone() {
+        assert_eq!(normalize_requirement("fmt"), "fmt");
+    }
+
+    /// Stand-ins for Conan, CMake and Ninja: Conan writes an (empty)
+    /// toolchain, CMake "builds" a program printing the expected output.
+    #[cfg(unix)]
+    const SMOKE_SHIMS: &[(&str, &str)] = &[
+        ("conan", r#"#!/bin/sh
+echo "conan $*" >> "$SHIM_LOG"
+case "$1" in
+install)
+    for arg in "$@"; do
+        case "$arg" in --output-folder=*) out="${arg#--output-folder=}";; esac
+    done
+    mkdir -p "$out" && echo "set(CMAKE_CXX_STANDARD 17)" > "$out/conan_toolchain.cmake";;
+profile) echo default;;
+esac
+"#),
+        ("cmake", r#"#!/bin/sh
+echo "cmake $*" >> "$SHIM_LOG"
+case "$1" in
+--version) echo "cmake version 3.28.0";;
+--build)
+    mkdir -p "$2/sage_doctor"
+    printf '#!/bin/sh\necho "Hello, world!"\n' > "$2/sage_doctor/sage_doctor"
+    chmod +x "$2/sage_doctor/sage_doctor";;
+*)
+    while [ $# -gt 0 ]; do
+        [ "$1" = "-B" ] && mkdir -p "$2" && touch "$2/CMakeCache.txt"
+        shift
+    done;;
+esac
+"#),
+        ("ninja", "#!/bin/sh\necho 1.11.1\n"),
+    ];
+
+    #[cfg(unix)]
+    #[test]
+    fn smoke_test_passes_without_dependencies() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = env::temp_dir().join(format!("sage-smoke-test-{}", std::process::id()));
+        let (shims, work) = (root.join("bin"), root.join("work"));
+        fs::create_dir_all(&shims).unwrap();
+        fs::create_dir_all(&work).unwrap();
+        for (name, script) in SMOKE_SHIMS {
+            let path = shims.join(name);
+            fs::write(&path, script).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let log = root.join("log");
+        let path = env::join_paths(std::iter::once(shims.clone()).chain(env::split_paths(&env::var_os("PATH").unwrap_or_default()))).unwrap();
+        env::set_var("PATH", path);
+        env::set_var("SHIM_LOG", &log);
+
+        let original_dir = env::current_dir().unwrap();
+        env::set_current_dir(&work).unwrap();
+        let result = smoke_test_stages();
+        env::set_current_dir(original_dir).unwrap();
+
+        let log = fs::read_to_string(&log).unwrap_or_default();
+        let _ = fs::remove_dir_all(&root);
+        assert!(result.is_ok(), "smoke test failed: {:?}\n{}", result, log);
+        assert!(log.lines().any(|line| line.starts_with("conan install")), "conan install did not run:\n{}", log);
+        assert!(log.contains("-DCMAKE_TOOLCHAIN_FILE="), "configure did not use the Conan toolchain:\n{}", log);
+    }
+}
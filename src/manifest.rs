@@ -0,0 +1,233 @@
+use colored::*;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::version::VersionReq;
+
+pub const MANIFEST_FILE: &str = "sage.toml";
+
+/// Default comments delimiting the block `sage install` rewrites in CMakeLists.txt.
+pub const DEPENDENCIES_START: &str = "# cppsage:dependencies_start";
+pub const DEPENDENCIES_END: &str = "# cppsage:dependencies_end";
+
+/// C++ standards accepted for `package.cpp_std`.
+pub const CPP_STANDARDS: &[u32] = &[11, 14, 17, 20, 23];
+
+/// CMake generators accepted for `build.generator`.
+pub const GENERATORS: &[&str] = &[
+    "Ninja",
+    "Ninja Multi-Config",
+    "Unix Makefiles",
+    "NMake Makefiles",
+    "MinGW Makefiles",
+    "Visual Studio 16 2019",
+    "Visual Studio 17 2022",
+    "Xcode",
+];
+
+/// The optional `sage.toml` project manifest.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub package: Option<Package>,
+    #[serde(default)]
+    pub build: Build,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    pub run: Run,
+    #[serde(default)]
+    pub install: Install,
+    /// Minimum tool versions the project needs, e.g. `cmake = ">=3.21"`.
+    #[serde(default)]
+    pub tools: BTreeMap<String, VersionReq>,
+    #[serde(default)]
+    pub layout: Layout,
+    #[serde(default)]
+    pub markers: Markers,
+    /// Command shortcuts, e.g. `rel = "compile --link-jobs 2"` for `sage rel`.
+    #[serde(default)]
+    pub alias: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Package {
+    pub name: String,
+    pub version: Option<String>,
+    #[serde(default, deserialize_with = "cpp_std")]
+    pub cpp_std: Option<u32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Build {
+    #[serde(default, deserialize_with = "generator")]
+    pub generator: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Run {
+    /// The executable target `sage run` last picked in a multi-binary project.
+    pub target: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Install {
+    /// The Conan profile `sage install` always uses.
+    pub profile: Option<String>,
+    /// The Conan build profile, when it differs from the default one.
+    pub build_profile: Option<String>,
+}
+
+/// Where a target keeps its sources and headers, relative to the `<name>/`
+/// directory the top-level CMakeLists adds with `add_subdirectory()`.
+#[derive(Deserialize, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub struct Layout {
+    pub source_dir: String,
+    pub include_dir: String,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout { source_dir: "src".to_string(), include_dir: "include".to_string() }
+    }
+}
+
+impl Layout {
+    /// Checks that the configured directories exist under `target_dir`.
+    pub fn validate(&self, target_dir: &Path) -> Result<(), std::io::Error> {
+        for (key, dir) in [("source_dir", &self.source_dir), ("include_dir", &self.include_dir)] {
+            if !target_dir.join(dir).is_dir() {
+                return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("[layout] {} '{}' does not exist in {}.", key, dir, target_dir.display())));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The comments delimiting the generated dependency block, shared by the
+/// scaffolding and `sage install` so the two always agree.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Markers {
+    #[serde(deserialize_with = "marker")]
+    pub dependencies_start: String,
+    #[serde(deserialize_with = "marker")]
+    pub dependencies_end: String,
+}
+
+impl Default for Markers {
+    fn default() -> Self {
+        Markers { dependencies_start: DEPENDENCIES_START.to_string(), dependencies_end: DEPENDENCIES_END.to_string() }
+    }
+}
+
+impl Manifest {
+    /// The `[dependencies]` table as Conan references, e.g. `fmt = "10.2.1"`
+    /// becomes `fmt/10.2.1`.
+    pub fn dependency_references(&self) -> Vec<String> {
+        self.dependencies.iter().map(|(name, version)| format!("{}/{}", name, version.trim())).collect()
+    }
+}
+
+/// Loads `sage.toml` from `dir`, returning `None` when the project has no manifest.
+pub fn load(dir: &Path) -> Result<Option<Manifest>, std::io::Error> {
+    let path = dir.join(MANIFEST_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)?;
+    toml::from_str(&content)
+        .map(Some)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid {}:\n{}", MANIFEST_FILE, e)))
+}
+
+/// Sets `[table] key = value` in `dir`'s `sage.toml`, creating the file when
+/// needed and preserving the formatting and comments of everything else.
+pub fn set_value(dir: &Path, table: &str, key: &str, value: &str) -> Result<(), std::io::Error> {
+    let path = dir.join(MANIFEST_FILE);
+    let content = if path.exists() { fs::read_to_string(&path)? } else { String::new() };
+    let mut document = content
+        .parse::<toml_edit::DocumentMut>()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid {}:\n{}", MANIFEST_FILE, e)))?;
+    if !document.contains_key(table) {
+        document[table] = toml_edit::table();
+    }
+    document[table][key] = toml_edit::value(value);
+    fs::write(path, document.to_string())
+}
+
+/// Validates `sage.toml` in the current directory, reporting unknown keys, type
+/// mismatches and invalid values together with their location in the file.
+pub fn check_config() -> Result<(), std::io::Error> {
+    let Some(manifest) = load(Path::new("."))? else {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} not found. Are you in the project root?", MANIFEST_FILE)));
+    };
+
+    if let Some(package) = &manifest.package {
+        let version = package.version.as_deref().unwrap_or("unversioned");
+        println!("- {}: {} {}", "package".bold(), package.name, version.dimmed());
+        if let Some(cpp_std) = package.cpp_std {
+            println!("- {}: C++{}", "cpp_std".bold(), cpp_std);
+        }
+    }
+    if let Some(generator) = &manifest.build.generator {
+        println!("- {}: {}", "generator".bold(), generator);
+    }
+    println!("- {}: {}", "dependencies".bold(), manifest.dependencies.len());
+    if let Some(profile) = &manifest.install.profile {
+        println!("- {}: {}", "conan profile".bold(), profile);
+    }
+    if let Some(profile) = &manifest.install.build_profile {
+        println!("- {}: {}", "conan build profile".bold(), profile);
+    }
+    if manifest.layout != Layout::default() {
+        let project_name = std::env::current_dir()?.file_name().unwrap().to_str().unwrap().to_string();
+        manifest.layout.validate(Path::new(&project_name))?;
+        println!("- {}: sources in {}, headers in {}", "layout".bold(), manifest.layout.source_dir, manifest.layout.include_dir);
+    }
+    for name in manifest.alias.keys() {
+        if <crate::Cli as clap::CommandFactory>::command().find_subcommand(name).is_some() {
+            println!("{} alias '{}' is shadowed by the built-in command and will never run", "Warning:".yellow(), name);
+        }
+    }
+    if !manifest.alias.is_empty() {
+        println!("- {}: {}", "aliases".bold(), manifest.alias.keys().cloned().collect::<Vec<_>>().join(", "));
+    }
+    if !manifest.tools.is_empty() {
+        println!("- {}: {}", "tools".bold(), manifest.tools.keys().cloned().collect::<Vec<_>>().join(", "));
+    }
+
+    println!("{} {} is valid.", "Success:".green(), MANIFEST_FILE);
+    Ok(())
+}
+
+fn cpp_std<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    let value = u32::deserialize(deserializer)?;
+    if !CPP_STANDARDS.contains(&value) {
+        return Err(serde::de::Error::custom(format!("unsupported C++ standard {}, expected one of {:?}", value, CPP_STANDARDS)));
+    }
+    Ok(Some(value))
+}
+
+fn generator<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    if !GENERATORS.contains(&value.as_str()) {
+        return Err(serde::de::Error::custom(format!("unknown generator '{}', expected one of {:?}", value, GENERATORS)));
+    }
+    Ok(Some(value))
+}
+
+fn marker<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    if !value.starts_with('#') || value.contains('\n') {
+        return Err(serde::de::Error::custom(format!("marker '{}' must be a single-line CMake comment starting with '#'", value)));
+    }
+    Ok(value)
+}
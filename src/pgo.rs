@@ -0,0 +1,151 @@
+use colored::*;
+use std::fs;
+use std::path::Path;
+
+use crate::compiler::Compiler;
+use crate::{buildenv, cmake_path, events};
+
+/// Where instrumented builds write their profile data, inside the build dir.
+const PROFILE_DIR: &str = "pgo-data";
+/// The merged Clang profile `--pgo-use` compiles against.
+const CLANG_PROFDATA: &str = "default.profdata";
+/// Touched after each `--pgo-use` build, to spot profile data collected since.
+const USED_STAMP: &str = ".sage-pgo-used";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Generate,
+    Use,
+}
+
+/// The compiler flags for a PGO `stage`, or nothing (with a warning) for
+/// compilers whose PGO flow sage doesn't drive. For `Use`, Clang's raw
+/// profiles are merged first, and missing profile data is an error.
+pub fn flags(compiler: Compiler, build_dir: &str, stage: Stage) -> Result<Vec<String>, std::io::Error> {
+    let profile_dir = std::env::current_dir()?.join(build_dir).join(PROFILE_DIR);
+    match compiler {
+        Compiler::Msvc => {
+            events::status(format!("{} MSVC's PGO flow (/GL, /LTCG:PGINSTRUMENT and pgomgr) isn't supported, ignoring --pgo-generate/--pgo-use", "Warning:".yellow()));
+            return Ok(Vec::new());
+        }
+        Compiler::Unknown => {
+            events::status(format!("{} Can't tell the PGO flags for this compiler, ignoring --pgo-generate/--pgo-use", "Warning:".yellow()));
+            return Ok(Vec::new());
+        }
+        Compiler::Gcc | Compiler::Clang => {}
+    }
+
+    if stage == Stage::Generate {
+        fs::create_dir_all(&profile_dir)?;
+        return Ok(vec![format!("-fprofile-generate={}", cmake_path(&profile_dir))]);
+    }
+
+    let no_profile = || {
+        std::io::Error::new(std::io::ErrorKind::NotFound, format!(
+            "No profile data in {}. Build with --pgo-generate and run representative workloads first.",
+            Path::new(build_dir).join(PROFILE_DIR).display()
+        ))
+    };
+    if compiler == Compiler::Gcc {
+        if !has_files_with_extension(&profile_dir, "gcda") {
+            return Err(no_profile());
+        }
+        // -fprofile-correction tolerates the inconsistent counters that
+        // multithreaded programs leave behind.
+        return Ok(vec![
+            format!("-fprofile-use={}", cmake_path(&profile_dir)),
+            "-fprofile-correction".to_string(),
+            "-Wno-missing-profile".to_string(),
+        ]);
+    }
+
+    let profdata = profile_dir.join(CLANG_PROFDATA);
+    if has_files_with_extension(&profile_dir, "profraw") {
+        merge_clang_profiles(&profile_dir, &profdata)?;
+    }
+    if !profdata.is_file() {
+        return Err(no_profile());
+    }
+    Ok(vec![format!("-fprofile-use={}", cmake_path(&profdata))])
+}
+
+/// Whether the profile data changed since the last `--pgo-use` build. Ninja
+/// doesn't track profile files, so the objects must then be rebuilt from
+/// scratch to pick the new profile up.
+pub fn profile_changed(build_dir: &str) -> bool {
+    let profile_dir = Path::new(build_dir).join(PROFILE_DIR);
+    let Some(newest) = newest_modification(&profile_dir) else {
+        return false;
+    };
+    let used = fs::metadata(profile_dir.join(USED_STAMP)).and_then(|m| m.modified()).ok();
+    used.is_none_or(|used| newest > used)
+}
+
+/// Records that the current profile data has been compiled in.
+pub fn mark_used(build_dir: &str) -> Result<(), std::io::Error> {
+    fs::write(Path::new(build_dir).join(PROFILE_DIR).join(USED_STAMP), "")
+}
+
+/// Tells the user how to continue after an instrumented build.
+pub fn print_next_step() {
+    events::status(format!("{} This build is instrumented for PGO. Run representative workloads, e.g. {}, then rebuild with {}",
+        "Note:".yellow(), "sage run --pgo-generate".cyan(), "sage compile --pgo-use".cyan()));
+}
+
+/// Merges Clang's per-process `.profraw` files into the single indexed
+/// profile `-fprofile-use` expects.
+fn merge_clang_profiles(profile_dir: &Path, profdata: &Path) -> Result<(), std::io::Error> {
+    let raw_profiles: Vec<_> = fs::read_dir(profile_dir)?
+        .map_while(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "profraw"))
+        .collect();
+
+    events::status(format!("Merging {} raw profile(s) with llvm-profdata...", raw_profiles.len()).dimmed());
+    let output = buildenv::tool_command("llvm-profdata")
+        .args(["merge", "-output"])
+        .arg(profdata)
+        .args(&raw_profiles)
+        .output()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => std::io::Error::new(e.kind(), "llvm-profdata not found in PATH; it is needed to merge Clang's profile data."),
+            _ => e,
+        })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(std::io::Error::other(format!("llvm-profdata merge failed:\n{}", stderr)));
+    }
+    Ok(())
+}
+
+/// Searches recursively: older GCCs mirror the object tree under the profile
+/// directory instead of mangling paths into file names.
+fn has_files_with_extension(dir: &Path, extension: &str) -> bool {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return false;
+    };
+    entries.map_while(Result::ok).any(|entry| {
+        let path = entry.path();
+        if path.is_dir() {
+            has_files_with_extension(&path, extension)
+        } else {
+            path.extension().is_some_and(|ext| ext == extension)
+        }
+    })
+}
+
+fn newest_modification(dir: &Path) -> Option<std::time::SystemTime> {
+    fs::read_dir(dir)
+        .ok()?
+        .map_while(Result::ok)
+        .filter(|entry| entry.file_name() != USED_STAMP)
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.is_dir() {
+                newest_modification(&path)
+            } else {
+                entry.metadata().and_then(|m| m.modified()).ok()
+            }
+        })
+        .max()
+}
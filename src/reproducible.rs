@@ -0,0 +1,77 @@
+use colored::*;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use crate::{backend, compile_project_in, events, targets, CompileArgs};
+
+/// Prints the SHA-256 of every executable target built into `build_dir`.
+pub fn print_hashes(build_dir: &Path) -> Result<(), std::io::Error> {
+    let hashes = executable_hashes(build_dir)?;
+    if hashes.is_empty() {
+        events::status("No built executables found to hash.".yellow());
+    }
+    for (target, hash) in hashes {
+        events::status(format!("{}  {}", hash, target.bold()));
+    }
+    Ok(())
+}
+
+/// Builds the project twice in fresh build directories and compares the
+/// hashes of the resulting executables to detect nondeterministic builds.
+pub fn verify(args: &CompileArgs) -> Result<(), std::io::Error> {
+    let mut runs = Vec::new();
+    for build_dir in ["build/reproducible-a", "build/reproducible-b"] {
+        if Path::new(build_dir).exists() {
+            fs::remove_dir_all(build_dir)?;
+        }
+        events::status(format!("{} {}", "Clean build in".green(), build_dir.bold()));
+        compile_project_in(build_dir, args)?;
+        runs.push(executable_hashes(Path::new(build_dir))?);
+    }
+
+    let (first, second) = (&runs[0], &runs[1]);
+    if first.is_empty() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "No built executables found to compare."));
+    }
+
+    let mut mismatches = 0;
+    for (target, hash) in first {
+        match second.get(target) {
+            Some(other) if other == hash => println!("- {}: {} {}", target.bold(), "match".green(), hash.dimmed()),
+            Some(other) => {
+                mismatches += 1;
+                println!("- {}: {}", target.bold(), "MISMATCH".red());
+                println!("    {}\n    {}", hash, other);
+            }
+            None => {
+                mismatches += 1;
+                println!("- {}: {}", target.bold(), "missing from second build".red());
+            }
+        }
+    }
+
+    if mismatches > 0 {
+        return Err(std::io::Error::other(format!("Build is not reproducible: {} executable(s) differ.", mismatches)));
+    }
+    events::status(format!("{} Build is reproducible.", "Success:".green()));
+    Ok(())
+}
+
+fn executable_hashes(build_dir: &Path) -> Result<BTreeMap<String, String>, std::io::Error> {
+    let project_name = env::current_dir()?.file_name().unwrap().to_str().unwrap().to_string();
+    let mut hashes = BTreeMap::new();
+    for target in backend::detect(Path::new(".")).executable_targets(Path::new("."), &project_name) {
+        if let Some(path) = targets::find_executable(build_dir, &target, None) {
+            hashes.insert(target, sha256_file(&path)?);
+        }
+    }
+    Ok(hashes)
+}
+
+fn sha256_file(path: &Path) -> Result<String, std::io::Error> {
+    let digest = Sha256::digest(fs::read(path)?);
+    Ok(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
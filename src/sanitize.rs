@@ -0,0 +1,81 @@
+use clap::ValueEnum;
+use colored::*;
+use std::env;
+
+use crate::compiler::Compiler;
+use crate::events;
+
+/// The runtime checks `--sanitize` builds with.
+#[derive(Clone, Copy, PartialEq, Debug, ValueEnum)]
+pub enum Sanitizer {
+    /// AddressSanitizer: out-of-bounds accesses, use-after-free, leaks
+    Address,
+    /// UndefinedBehaviorSanitizer: overflow, misaligned or null accesses, bad casts
+    Undefined,
+    /// ThreadSanitizer: data races
+    Thread,
+    /// LeakSanitizer on its own, without ASan's slowdown
+    Leak,
+}
+
+impl Sanitizer {
+    /// The build directory the sanitized build gets inside `build/`, so it
+    /// never shares objects with the normal build.
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            Sanitizer::Address => "asan",
+            Sanitizer::Undefined => "ubsan",
+            Sanitizer::Thread => "tsan",
+            Sanitizer::Leak => "lsan",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Sanitizer::Address => "address",
+            Sanitizer::Undefined => "undefined",
+            Sanitizer::Thread => "thread",
+            Sanitizer::Leak => "leak",
+        }
+    }
+
+    /// The runtime's options variable and sage's defaults for it: full
+    /// stack traces, and stopping at the first UB report instead of
+    /// scrolling past it.
+    fn default_options(self) -> (&'static str, &'static str) {
+        match self {
+            Sanitizer::Address => ("ASAN_OPTIONS", "detect_stack_use_after_return=1:check_initialization_order=1:strict_init_order=1"),
+            Sanitizer::Undefined => ("UBSAN_OPTIONS", "print_stacktrace=1:halt_on_error=1"),
+            Sanitizer::Thread => ("TSAN_OPTIONS", "second_deadlock_stack=1"),
+            Sanitizer::Leak => ("LSAN_OPTIONS", "report_objects=1"),
+        }
+    }
+}
+
+/// The compile flags for `sanitizer`, which CMake also passes when linking
+/// executables. MSVC only has AddressSanitizer; other combinations get a
+/// warning and no flags.
+pub fn flags(compiler: Compiler, sanitizer: Sanitizer) -> Vec<String> {
+    match compiler {
+        Compiler::Gcc | Compiler::Clang => vec![format!("-fsanitize={}", sanitizer.name()), "-fno-omit-frame-pointer".to_string()],
+        Compiler::Msvc if sanitizer == Sanitizer::Address => vec!["/fsanitize=address".to_string()],
+        Compiler::Msvc => {
+            events::status(format!("{} MSVC only supports --sanitize address, ignoring --sanitize {}", "Warning:".yellow(), sanitizer.name()));
+            Vec::new()
+        }
+        Compiler::Unknown => {
+            events::status(format!("{} Can't tell the sanitizer flags for this compiler, ignoring --sanitize", "Warning:".yellow()));
+            Vec::new()
+        }
+    }
+}
+
+/// The runtime options to run a sanitized program with, unless the
+/// environment already sets them.
+pub fn runtime_env(sanitizer: Sanitizer) -> Vec<(String, String)> {
+    let (name, options) = sanitizer.default_options();
+    if env::var_os(name).is_some() {
+        return Vec::new();
+    }
+    vec![(name.to_string(), options.to_string())]
+}
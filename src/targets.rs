@@ -0,0 +1,219 @@
+use colored::*;
+use std::fs;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{backend, cmake, configure_project, events, manifest};
+
+/// Lists the configured project's buildable targets, grouped by kind, using the
+/// CMake file API so executables can be told apart from libraries.
+pub fn print_targets() -> Result<(), std::io::Error> {
+    if backend::is_meson_project(Path::new(".")) {
+        return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Listing targets is only supported for CMake projects."));
+    }
+    let build_dir = Path::new("build");
+    if !build_dir.join("CMakeCache.txt").exists() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "The project is not configured yet. Run 'sage compile' first."));
+    }
+
+    let api_dir = build_dir.join(".cmake/api/v1");
+    let query = api_dir.join("query/codemodel-v2");
+    if !query.exists() || latest_reply_index(&api_dir.join("reply")).is_none() {
+        // CMake only answers file API queries while configuring.
+        fs::create_dir_all(query.parent().unwrap())?;
+        fs::write(&query, "")?;
+        configure_project("build", &[], None, None)?;
+    }
+
+    let reply_dir = api_dir.join("reply");
+    let targets = latest_reply_index(&reply_dir)
+        .and_then(|index| codemodel_targets(&reply_dir, &index))
+        .ok_or_else(|| std::io::Error::other("Could not read the CMake file API reply in build/.cmake/api/v1/reply."))?;
+
+    let groups = [
+        ("Executables", vec!["EXECUTABLE"]),
+        ("Libraries", vec!["STATIC_LIBRARY", "SHARED_LIBRARY", "MODULE_LIBRARY", "OBJECT_LIBRARY", "INTERFACE_LIBRARY"]),
+        ("Other", vec!["UTILITY"]),
+    ];
+    for (title, kinds) in groups {
+        let names: Vec<&(String, String)> = targets.iter().filter(|(_, kind)| kinds.contains(&kind.as_str())).collect();
+        if names.is_empty() {
+            continue;
+        }
+        println!("{}", title.bold());
+        for (name, kind) in names {
+            let name = if title == "Executables" { name.green() } else { name.normal() };
+            println!("  {} {}", name, format!("({})", kind.to_lowercase().replace('_', " ")).dimmed());
+        }
+    }
+    Ok(())
+}
+
+/// The newest `index-*.json` in the file API reply directory.
+fn latest_reply_index(reply_dir: &Path) -> Option<PathBuf> {
+    fs::read_dir(reply_dir)
+        .ok()?
+        .map_while(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with("index-") && n.ends_with(".json")))
+        .max()
+}
+
+/// Reads `(name, type)` for every target of the first configuration.
+fn codemodel_targets(reply_dir: &Path, index: &Path) -> Option<Vec<(String, String)>> {
+    let read_json = |path: &Path| -> Option<serde_json::Value> { serde_json::from_str(&fs::read_to_string(path).ok()?).ok() };
+
+    let index = read_json(index)?;
+    let codemodel = read_json(&reply_dir.join(index["reply"]["codemodel-v2"]["jsonFile"].as_str()?))?;
+    let mut targets = Vec::new();
+    for target in codemodel["configurations"][0]["targets"].as_array()? {
+        let name = target["name"].as_str()?.to_string();
+        let kind = target["jsonFile"]
+            .as_str()
+            .and_then(|file| read_json(&reply_dir.join(file)))
+            .and_then(|details| details["type"].as_str().map(str::to_string))
+            .unwrap_or_else(|| "UTILITY".to_string());
+        targets.push((name, kind));
+    }
+    targets.sort();
+    Some(targets)
+}
+
+/// Lists the executable targets declared with `add_executable()` in the
+/// project's CMakeLists files, in declaration order.
+pub fn executable_targets(root: &Path, project_name: &str) -> Vec<String> {
+    let mut targets = Vec::new();
+    for cmake_file in cmake::cmake_lists_files(root) {
+        let Ok(content) = fs::read_to_string(&cmake_file) else {
+            continue;
+        };
+        for target in parse_add_executable(&content, project_name) {
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+    }
+    targets
+}
+
+/// The configurations CMake's multi-config generators build into.
+const CONFIGURATIONS: &[&str] = &["Debug", "Release", "RelWithDebInfo", "MinSizeRel"];
+
+/// Finds the built binary for `target` under `build_dir`, preferring the
+/// shallowest match so nested CMake scratch directories never win. Multi-config
+/// generators build one binary per configuration into `<config>/` (e.g.
+/// `build/app/Debug/app.exe`); with a `config`, a binary in that directory
+/// wins at any depth over one left behind by a single-config build or another
+/// configuration, which is only used as a last resort.
+pub fn find_executable(build_dir: &Path, target: &str, config: Option<&str>) -> Option<PathBuf> {
+    let file_name = if cfg!(target_os = "windows") {
+        format!("{}.exe", target)
+    } else {
+        target.to_string()
+    };
+
+    // Every match, shallowest first.
+    let mut found = Vec::new();
+    let mut level = vec![build_dir.to_path_buf()];
+    while !level.is_empty() {
+        let mut next = Vec::new();
+        for dir in level {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.map_while(Result::ok) {
+                let path = entry.path();
+                if path.is_dir() {
+                    if entry.file_name() != "CMakeFiles" {
+                        next.push(path);
+                    }
+                } else if entry.file_name().to_string_lossy() == file_name {
+                    found.push(path);
+                }
+            }
+        }
+        level = next;
+    }
+
+    let parent_name = |path: &PathBuf| path.parent().and_then(|p| p.file_name()).map(|name| name.to_string_lossy().into_owned());
+    let Some(config) = config else {
+        return found.into_iter().next();
+    };
+    if let Some(path) = found.iter().find(|path| parent_name(path).as_deref() == Some(config)) {
+        return Some(path.clone());
+    }
+    let fallback = found.iter().find(|path| !parent_name(path).is_some_and(|name| CONFIGURATIONS.contains(&name.as_str()))).or(found.first())?;
+    events::status(format!("{} No {} build of '{}' found, using {}", "Warning:".yellow(), config, target, fallback.display()));
+    Some(fallback.clone())
+}
+
+/// Picks the executable target to run: the explicit `--target`, the only
+/// target, or an interactive choice that is remembered in `sage.toml`.
+pub fn select_target(targets: &[String], requested: Option<&str>) -> Result<String, std::io::Error> {
+    if let Some(requested) = requested {
+        if targets.is_empty() || targets.iter().any(|t| t == requested) {
+            return Ok(requested.to_string());
+        }
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("No executable target named '{}'. Available targets: {}", requested, targets.join(", "))));
+    }
+
+    match targets {
+        [] => Err(std::io::Error::new(std::io::ErrorKind::NotFound, "No add_executable() targets found in the project's CMakeLists.txt files.")),
+        [only] => Ok(only.clone()),
+        _ => {
+            if !std::io::stdin().is_terminal() {
+                return Err(std::io::Error::other(format!("Multiple executable targets found, pass one with --target: {}", targets.join(", "))));
+            }
+            let remembered = manifest::load(Path::new("."))?
+                .and_then(|m| m.run.target)
+                .filter(|t| targets.contains(t));
+            let choice = prompt_for_target(targets, remembered.as_deref())?;
+            manifest::set_value(Path::new("."), "run", "target", &choice)?;
+            Ok(choice)
+        }
+    }
+}
+
+fn prompt_for_target(targets: &[String], default: Option<&str>) -> Result<String, std::io::Error> {
+    println!("{}", "Multiple executable targets found:".yellow());
+    for (index, target) in targets.iter().enumerate() {
+        let marker = if Some(target.as_str()) == default { " (last used)".dimmed().to_string() } else { String::new() };
+        println!("  {}) {}{}", index + 1, target, marker);
+    }
+
+    loop {
+        match default {
+            Some(default) => print!("Select a target [{}]: ", default),
+            None => print!("Select a target: "),
+        }
+        std::io::stdout().flush()?;
+
+        let mut input = String::new();
+        if std::io::stdin().read_line(&mut input)? == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "No target selected."));
+        }
+        let input = input.trim();
+
+        if input.is_empty() {
+            if let Some(default) = default {
+                return Ok(default.to_string());
+            }
+        } else if let Some(target) = input.parse::<usize>().ok().and_then(|n| targets.get(n.wrapping_sub(1))) {
+            return Ok(target.clone());
+        } else if let Some(target) = targets.iter().find(|t| *t == input) {
+            return Ok(target.clone());
+        }
+        println!("{} Enter a number between 1 and {}.", "Invalid choice.".red(), targets.len());
+    }
+}
+
+/// Extracts target names from `add_executable(<name> ...)` calls, skipping
+/// IMPORTED and ALIAS targets which don't produce a binary of their own.
+fn parse_add_executable(content: &str, project_name: &str) -> Vec<String> {
+    cmake::command_arguments(content, "add_executable")
+        .into_iter()
+        .filter(|arguments| !arguments.iter().any(|a| a == "IMPORTED" || a == "ALIAS"))
+        .filter_map(|arguments| arguments.into_iter().next())
+        .map(|name| name.replace("${PROJECT_NAME}", project_name))
+        .collect()
+}
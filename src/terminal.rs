@@ -0,0 +1,112 @@
+use colored::*;
+use std::env;
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+
+/// What the terminal sage writes to can display.
+pub struct Capabilities {
+    pub is_terminal: bool,
+    /// ANSI escape sequences (colors) are interpreted rather than shown.
+    pub ansi: bool,
+    /// Output is decoded as UTF-8.
+    pub utf8: bool,
+}
+
+/// Detects the terminal's capabilities once, enabling virtual terminal
+/// processing on Windows consoles along the way, and turns colors off when
+/// escape codes would show up as garbage. `NO_COLOR`/`CLICOLOR_FORCE` keep
+/// working as before.
+pub fn init() -> &'static Capabilities {
+    static CAPABILITIES: OnceLock<Capabilities> = OnceLock::new();
+    CAPABILITIES.get_or_init(|| {
+        let is_terminal = std::io::stdout().is_terminal();
+        let capabilities = Capabilities { is_terminal, ansi: is_terminal && supports_ansi(), utf8: supports_utf8() };
+        let forced = env::var("CLICOLOR_FORCE").is_ok_and(|value| value != "0");
+        if is_terminal && !capabilities.ansi && !forced {
+            colored::control::set_override(false);
+        }
+        capabilities
+    })
+}
+
+/// The "Terminal" section of `sage doctor`.
+pub fn report() {
+    println!("\n{}", "Terminal".bold().underline());
+    let capabilities = init();
+    if !capabilities.is_terminal {
+        println!("- {}: {}", "Output".bold(), "not a terminal (colors are off unless CLICOLOR_FORCE is set)".dimmed());
+    } else if capabilities.ansi {
+        println!("- {}: {}", "ANSI colors".bold(), "supported".green());
+    } else {
+        println!("- {}: {}", "ANSI colors".bold(), "not supported, using plain output".yellow());
+        println!("  {}", ansi_hint().cyan());
+    }
+    if env::var_os("NO_COLOR").is_some() {
+        println!("- {}: {}", "NO_COLOR".bold(), "set, colors are off".dimmed());
+    }
+    if capabilities.utf8 {
+        println!("- {}: {}", "UTF-8".bold(), "yes".green());
+    } else {
+        println!("- {}: {}", "UTF-8".bold(), "no".yellow());
+        println!("  {}", utf8_hint().cyan());
+    }
+}
+
+#[cfg(windows)]
+fn supports_ansi() -> bool {
+    use windows_sys::Win32::System::Console::{GetConsoleMode, GetStdHandle, SetConsoleMode, ENABLE_VIRTUAL_TERMINAL_PROCESSING, STD_OUTPUT_HANDLE};
+
+    // MSYS2/Cygwin terminals (mintty) aren't consoles but understand ANSI.
+    if env::var("TERM").is_ok_and(|term| term != "dumb") {
+        return true;
+    }
+    // Legacy consoles (before Windows 10) reject the virtual terminal flag.
+    unsafe {
+        let handle = GetStdHandle(STD_OUTPUT_HANDLE);
+        let mut mode = 0;
+        if GetConsoleMode(handle, &mut mode) == 0 {
+            return false;
+        }
+        mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0 || SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
+    }
+}
+
+#[cfg(not(windows))]
+fn supports_ansi() -> bool {
+    env::var("TERM").is_ok_and(|term| !term.is_empty() && term != "dumb")
+}
+
+#[cfg(windows)]
+fn supports_utf8() -> bool {
+    const CP_UTF8: u32 = 65001;
+    unsafe { windows_sys::Win32::System::Console::GetConsoleOutputCP() == CP_UTF8 }
+}
+
+/// The locale's character set, from the first of `LC_ALL`, `LC_CTYPE` and
+/// `LANG` that is set, the same precedence the C library uses.
+#[cfg(not(windows))]
+fn supports_utf8() -> bool {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+        .is_some_and(|locale| {
+            let locale = locale.to_ascii_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        })
+}
+
+fn ansi_hint() -> &'static str {
+    if cfg!(windows) {
+        "Use Windows Terminal or a Windows 10+ console for colored output"
+    } else {
+        "Set TERM to your terminal's type (e.g. xterm-256color) for colored output"
+    }
+}
+
+fn utf8_hint() -> &'static str {
+    if cfg!(windows) {
+        "Switch the console to UTF-8 with: chcp 65001"
+    } else {
+        "Use a UTF-8 locale, e.g.: export LANG=C.UTF-8"
+    }
+}
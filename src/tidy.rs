@@ -0,0 +1,227 @@
+use clap::{Args, ValueEnum};
+use colored::*;
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{backend, collect_sources, configure_project, events, rebase_path};
+
+/// Translation units clang-tidy is run over.
+const SOURCE_EXTENSIONS: &[&str] = &["c", "cc", "cpp", "cxx", "mm"];
+
+#[derive(Args, Clone)]
+pub struct TidyArgs {
+    /// Write suggested fixes to a YAML file for review instead of applying them
+    #[arg(long, value_name = "FILE")]
+    export_fixes: Option<PathBuf>,
+    /// Run clang-tidy and apply the exported fixes with clang-apply-replacements
+    #[arg(long, conflicts_with = "export_fixes")]
+    apply_fixes: bool,
+    /// Let clang-tidy apply its suggested fixes directly (clang-tidy --fix)
+    #[arg(long, conflicts_with_all = ["export_fixes", "apply_fixes"])]
+    fix: bool,
+    /// Run only these checks, overriding .clang-tidy (comma-separated globs, e.g. "modernize-*,bugprone-*")
+    #[arg(long, value_name = "LIST", value_parser = parse_checks)]
+    checks: Option<String>,
+    /// Write the check set of these presets to .clang-tidy instead of running clang-tidy (comma-separated)
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "PRESET", conflicts_with_all = ["export_fixes", "apply_fixes", "fix", "checks"])]
+    init: Vec<CheckPreset>,
+}
+
+impl TidyArgs {
+    pub fn rebase_paths(&mut self, base: &Path) {
+        self.export_fixes.iter_mut().for_each(|path| rebase_path(path, base));
+    }
+}
+
+/// Check sets `sage tidy --init` can write to `.clang-tidy`.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum CheckPreset {
+    /// Suggest modern C++ replacements (modernize-*)
+    Modernize,
+    /// Catch bug-prone patterns (bugprone-*)
+    Bugprone,
+    /// Flag avoidable copies and slow constructs (performance-*)
+    Performance,
+    /// Readability conventions (readability-*)
+    Readability,
+    /// The C++ Core Guidelines (cppcoreguidelines-*)
+    Cppcoreguidelines,
+}
+
+impl CheckPreset {
+    fn pattern(self) -> &'static str {
+        match self {
+            CheckPreset::Modernize => "modernize-*",
+            CheckPreset::Bugprone => "bugprone-*",
+            CheckPreset::Performance => "performance-*",
+            CheckPreset::Readability => "readability-*",
+            CheckPreset::Cppcoreguidelines => "cppcoreguidelines-*",
+        }
+    }
+}
+
+/// Validates a `--checks` list, requiring at least one check pattern.
+fn parse_checks(value: &str) -> Result<String, String> {
+    let patterns: Vec<&str> = value.split(',').map(str::trim).filter(|p| !p.is_empty()).collect();
+    if patterns.is_empty() {
+        return Err("expected at least one check pattern, e.g. \"modernize-*\"".to_string());
+    }
+    if let Some(pattern) = patterns.iter().find(|p| p.contains(char::is_whitespace)) {
+        return Err(format!("check pattern '{}' contains whitespace", pattern));
+    }
+    Ok(patterns.join(","))
+}
+
+pub fn run_tidy(args: &TidyArgs) -> Result<(), std::io::Error> {
+    if !args.init.is_empty() {
+        return write_config(&args.init);
+    }
+
+    let build_dir = Path::new("build");
+    if !build_dir.join("compile_commands.json").exists() {
+        if backend::is_meson_project(Path::new(".")) {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "build/compile_commands.json not found. Run 'sage compile' first."));
+        }
+        // Configuring is enough: CMake writes the compile database without building.
+        configure_project("build", &[], None, None)?;
+    }
+
+    let sources = collect_sources(Path::new("."), SOURCE_EXTENSIONS);
+    if sources.is_empty() {
+        events::status("No source files to check.".yellow());
+        return Ok(());
+    }
+
+    // clang-apply-replacements consumes a directory of fix files, so give the
+    // fixes for --apply-fixes a directory of their own.
+    let fixes_dir = build_dir.join("tidy-fixes");
+    let export_path = if args.apply_fixes {
+        let _ = fs::remove_dir_all(&fixes_dir);
+        fs::create_dir_all(&fixes_dir)?;
+        Some(fixes_dir.join("fixes.yaml"))
+    } else {
+        args.export_fixes.clone()
+    };
+
+    events::status("Running clang-tidy...".green());
+    let mut command = Command::new("clang-tidy");
+    command.arg("-p").arg(build_dir);
+    if let Some(checks) = &args.checks {
+        // clang-tidy appends -checks to the config file's Checks, so disable
+        // those first to run exactly the requested set.
+        command.arg(format!("-checks=-*,{}", checks));
+    }
+    if let Some(path) = &export_path {
+        command.arg(format!("-export-fixes={}", path.display()));
+    }
+    if args.fix {
+        command.arg("--fix");
+    }
+    let output = command.args(&sources).output()?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    println!("{}", stdout);
+    print_summary(&stdout);
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(std::io::Error::other(format!("clang-tidy failed:\n{}", stderr)));
+    }
+
+    if args.apply_fixes {
+        apply_fixes(&fixes_dir)?;
+    } else if let Some(path) = &export_path {
+        events::status(format!("{} Fixes written to {}", "Success:".green(), path.display()));
+        events::status(format!("  Review them, then apply with: {}", format!("clang-apply-replacements {}", path.parent().unwrap_or(Path::new(".")).display()).cyan()));
+    }
+
+    Ok(())
+}
+
+/// Prints how many warnings clang-tidy reported, per check, most frequent first.
+fn print_summary(output: &str) {
+    let diagnostic = Regex::new(r"^(?P<file>(?:[A-Za-z]:)?[^:]+):\d+:\d+:\s*(?P<level>warning|error):.*?(?:\[(?P<check>[^\]]+)\])?$").unwrap();
+    let mut files = BTreeSet::new();
+    let mut warnings = 0;
+    let mut errors = 0;
+    let mut by_check: BTreeMap<String, usize> = BTreeMap::new();
+    for captures in output.lines().filter_map(|line| diagnostic.captures(line)) {
+        files.insert(captures["file"].to_string());
+        if &captures["level"] == "error" {
+            errors += 1;
+            continue;
+        }
+        warnings += 1;
+        // WarningsAsErrors turns `[check]` into `[check,-warnings-as-errors]`.
+        let check = captures.name("check").map_or("(no check name)", |c| c.as_str().split(',').next().unwrap_or_default());
+        *by_check.entry(check.to_string()).or_default() += 1;
+    }
+
+    if warnings == 0 && errors == 0 {
+        println!("{} clang-tidy reported no warnings.", "Success:".green());
+        return;
+    }
+    println!("{} {} warning(s) and {} error(s) in {} file(s)", "Summary:".bold(), warnings, errors, files.len());
+    let mut by_check: Vec<_> = by_check.into_iter().collect();
+    by_check.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    for (check, count) in by_check {
+        println!("  {:>5}  {}", count, check);
+    }
+}
+
+/// Sets the `Checks:` of `.clang-tidy` to the given presets, keeping the
+/// rest of an existing config.
+fn write_config(presets: &[CheckPreset]) -> Result<(), std::io::Error> {
+    let path = Path::new(".clang-tidy");
+    let mut patterns = vec!["-*"];
+    for preset in presets {
+        if !patterns.contains(&preset.pattern()) {
+            patterns.push(preset.pattern());
+        }
+    }
+    let checks_line = format!("Checks: '{}'", patterns.join(","));
+
+    let content = fs::read_to_string(path).unwrap_or_default();
+    let existing = content.lines().position(|line| line.starts_with("Checks:"));
+    let content = match existing {
+        Some(index) => {
+            let value = content.lines().nth(index).unwrap_or("")["Checks:".len()..].trim();
+            if value.is_empty() || value.starts_with(['>', '|']) {
+                return Err(std::io::Error::other(".clang-tidy has a multi-line Checks entry; edit it by hand."));
+            }
+            let mut lines: Vec<&str> = content.lines().collect();
+            lines[index] = &checks_line;
+            lines.join("\n") + "\n"
+        }
+        None if content.trim().is_empty() => format!("---\n{}\n", checks_line),
+        None => match content.strip_prefix("---\n") {
+            Some(rest) => format!("---\n{}\n{}", checks_line, rest),
+            None => format!("{}\n{}", checks_line, content),
+        },
+    };
+    fs::write(path, content)?;
+    events::status(format!("{} Wrote {} to {}", "Success:".green(), checks_line, path.display()));
+    Ok(())
+}
+
+fn apply_fixes(fixes_dir: &Path) -> Result<(), std::io::Error> {
+    if !fixes_dir.join("fixes.yaml").exists() {
+        events::status("clang-tidy suggested no fixes.".green());
+        return Ok(());
+    }
+
+    events::status("Applying fixes with clang-apply-replacements...".green());
+    let output = Command::new("clang-apply-replacements").arg(fixes_dir).output();
+    let _ = fs::remove_dir_all(fixes_dir);
+    let output = output?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(std::io::Error::other(format!("clang-apply-replacements failed:\n{}", stderr)));
+    }
+    events::status(format!("{} Fixes applied.", "Success:".green()));
+
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use colored::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::compiler::Compiler;
+use crate::{events, find_program};
+
+/// Where the per-translation-unit traces are gathered, inside the build dir.
+const TRACES_DIR: &str = "traces";
+
+/// `-ftime-trace` for Clang; other compilers have no equivalent, so they get
+/// a warning and no flag.
+pub fn flags(compiler: Compiler) -> Vec<String> {
+    if compiler != Compiler::Clang {
+        events::status(format!("{} --time-trace needs Clang's -ftime-trace, ignoring it for this compiler ({:?})", "Warning:".yellow(), compiler));
+        return Vec::new();
+    }
+    vec!["-ftime-trace".to_string()]
+}
+
+/// Copies the traces Clang wrote next to the object files into
+/// `<build_dir>/traces/`, and summarizes them with ClangBuildAnalyzer when it
+/// is installed.
+pub fn collect(build_dir: &str) -> Result<(), std::io::Error> {
+    let build_dir = Path::new(build_dir);
+    let traces_dir = build_dir.join(TRACES_DIR);
+    if traces_dir.exists() {
+        fs::remove_dir_all(&traces_dir)?;
+    }
+    let mut traces = Vec::new();
+    find_traces(build_dir, &traces_dir, &mut traces);
+    if traces.is_empty() {
+        events::status(format!("{} No -ftime-trace output found under {}", "Warning:".yellow(), build_dir.display()));
+        return Ok(());
+    }
+
+    fs::create_dir_all(&traces_dir)?;
+    for trace in &traces {
+        // CMakeFiles/app.dir/src/main.cpp.json -> app.dir-src-main.cpp.json
+        let relative = trace.strip_prefix(build_dir).unwrap_or(trace);
+        let name: Vec<String> = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .filter(|component| component != "CMakeFiles")
+            .collect();
+        fs::copy(trace, traces_dir.join(name.join("-")))?;
+    }
+    events::status(format!("{} {} time trace(s) written to {}", "Note:".yellow(), traces.len(), traces_dir.display()));
+    events::status(format!("  {}", "Open them in chrome://tracing or https://ui.perfetto.dev".cyan()));
+
+    if find_program("ClangBuildAnalyzer").is_some() {
+        analyze(&traces_dir)?;
+    }
+    Ok(())
+}
+
+/// Finds `*.json` files that sit next to an object file of the same name,
+/// which is where `-ftime-trace` puts them.
+fn find_traces(dir: &Path, traces_dir: &Path, traces: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.map_while(Result::ok).map(|entry| entry.path()) {
+        if path.is_dir() {
+            if path != traces_dir {
+                find_traces(&path, traces_dir, traces);
+            }
+        } else if path.extension().is_some_and(|ext| ext == "json") && ["o", "obj"].iter().any(|ext| path.with_extension(ext).is_file()) {
+            traces.push(path);
+        }
+    }
+}
+
+/// Runs ClangBuildAnalyzer over the collected traces, writing its report to
+/// `analysis.txt` next to them.
+fn analyze(traces_dir: &Path) -> Result<(), std::io::Error> {
+    events::status("Analyzing the traces with ClangBuildAnalyzer...".dimmed());
+    let capture = traces_dir.join("capture.bin");
+    let mut command = Command::new("ClangBuildAnalyzer");
+    command.arg("--all").arg(traces_dir).arg(&capture);
+    events::command(&command);
+    let output = command.output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        events::status(format!("{} ClangBuildAnalyzer --all failed: {}", "Warning:".yellow(), stderr.trim()));
+        return Ok(());
+    }
+    let mut command = Command::new("ClangBuildAnalyzer");
+    command.arg("--analyze").arg(&capture);
+    events::command(&command);
+    let output = command.output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        events::status(format!("{} ClangBuildAnalyzer --analyze failed: {}", "Warning:".yellow(), stderr.trim()));
+        return Ok(());
+    }
+    let report = traces_dir.join("analysis.txt");
+    fs::write(&report, &output.stdout)?;
+    events::status(format!("{} Build analysis written to {}", "Note:".yellow(), report.display()));
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use colored::*;
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+
+use crate::format::FORMAT_EXTENSIONS;
+use crate::{collect_sources, events};
+
+/// Scans the project's sources and headers (the files `sage fmt` touches) for
+/// `markers` as whole words and reports each hit as `file:line`. Only fails
+/// when `fail` is set; otherwise the build goes ahead.
+pub fn check(markers: &[String], fail: bool) -> Result<(), std::io::Error> {
+    let alternatives: Vec<String> = markers.iter().map(|marker| regex::escape(marker)).collect();
+    let pattern = Regex::new(&format!(r"\b({})\b", alternatives.join("|"))).map_err(std::io::Error::other)?;
+
+    let mut found = 0;
+    for source in collect_sources(Path::new("."), FORMAT_EXTENSIONS) {
+        // Skip files that aren't text rather than failing the build over them.
+        let Ok(content) = fs::read_to_string(&source) else {
+            continue;
+        };
+        for (index, line) in content.lines().enumerate() {
+            if pattern.is_match(line) {
+                let location = format!("{}:{}", source.strip_prefix(".").unwrap_or(&source).display(), index + 1);
+                events::status(format!("  {} {}", location.cyan(), line.trim()));
+                found += 1;
+            }
+        }
+    }
+
+    if found == 0 {
+        events::status(format!("No {} markers found.", markers.join("/")).dimmed());
+        return Ok(());
+    }
+    if fail {
+        return Err(std::io::Error::other(format!("{} {} marker(s) found, failing because of --fail-on-todo.", found, markers.join("/"))));
+    }
+    events::status(format!("{} {} {} marker(s) found", "Warning:".yellow(), found, markers.join("/")));
+    Ok(())
+}
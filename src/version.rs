@@ -0,0 +1,104 @@
+use serde::{Deserialize, Deserializer};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A dotted numeric version such as `3.21` or `1.11.1`; missing components
+/// compare as zero.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Version(Vec<u64>);
+
+impl Version {
+    pub fn parse(text: &str) -> Option<Version> {
+        let parts: Option<Vec<u64>> = text.trim().split('.').map(|part| part.parse().ok()).collect();
+        parts.filter(|parts| !parts.is_empty()).map(Version)
+    }
+
+    /// Finds the first version-looking token in tool output such as
+    /// `cmake version 3.28.3` or `Conan version 2.3.0`.
+    pub fn find_in(text: &str) -> Option<Version> {
+        text.split(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .map(|token| token.trim_matches('.'))
+            .filter(|token| token.contains('.'))
+            .find_map(Version::parse)
+    }
+
+    /// How far `newer` is ahead: "major", "minor" or "patch" after the first
+    /// component that differs.
+    pub fn gap(&self, newer: &Version) -> &'static str {
+        let len = self.0.len().max(newer.0.len());
+        match (0..len).find(|&i| self.0.get(i).unwrap_or(&0) != newer.0.get(i).unwrap_or(&0)) {
+            Some(0) => "major",
+            Some(1) => "minor",
+            _ => "patch",
+        }
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let len = self.0.len().max(other.0.len());
+        (0..len)
+            .map(|i| self.0.get(i).unwrap_or(&0).cmp(other.0.get(i).unwrap_or(&0)))
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts: Vec<String> = self.0.iter().map(|part| part.to_string()).collect();
+        write!(f, "{}", parts.join("."))
+    }
+}
+
+/// A comma-separated list of comparisons, e.g. `>=3.21, <4`.
+#[derive(Clone, Debug)]
+pub struct VersionReq(Vec<(String, Version)>);
+
+impl VersionReq {
+    pub fn parse(text: &str) -> Result<VersionReq, String> {
+        let mut comparisons = Vec::new();
+        for part in text.split(',') {
+            let part = part.trim();
+            let operator_len = part.len() - part.trim_start_matches(['>', '<', '=']).len();
+            let (operator, version) = part.split_at(operator_len);
+            let operator = match operator {
+                "" | "=" | "==" => "==",
+                ">=" | ">" | "<=" | "<" => operator,
+                _ => return Err(format!("invalid operator '{}' in '{}'", operator, text)),
+            };
+            let version = Version::parse(version).ok_or_else(|| format!("invalid version '{}' in '{}'", version.trim(), text))?;
+            comparisons.push((operator.to_string(), version));
+        }
+        Ok(VersionReq(comparisons))
+    }
+
+    pub fn matches(&self, version: &Version) -> bool {
+        self.0.iter().all(|(operator, required)| match operator.as_str() {
+            ">=" => version >= required,
+            ">" => version > required,
+            "<=" => version <= required,
+            "<" => version < required,
+            _ => version == required,
+        })
+    }
+}
+
+impl fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts: Vec<String> = self.0.iter().map(|(operator, version)| format!("{}{}", operator, version)).collect();
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+impl<'de> Deserialize<'de> for VersionReq {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        VersionReq::parse(&String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}